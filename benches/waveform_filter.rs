@@ -0,0 +1,52 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Throughput of [`WaveformFilter`] for a fixed signal
+//!
+//! Run with `cargo bench`.
+
+// Conversions between sample counts and floating-point values are
+// not critical here.
+#![allow(clippy::cast_precision_loss)]
+
+use std::{hint::black_box, time::Instant};
+
+use audio_viz::WaveformFilter;
+
+const SAMPLE_RATE_HZ: usize = 44_100;
+
+const SIGNAL_SECS: usize = 60;
+
+const ITERATIONS: u32 = 10;
+
+fn main() {
+    // Deterministic mix of a low and a high sine wave.
+    let samples = (0..SAMPLE_RATE_HZ * SIGNAL_SECS)
+        .map(|i| {
+            let t = i as f32 / SAMPLE_RATE_HZ as f32;
+            0.5 * (2.0 * std::f32::consts::PI * 100.0 * t).sin()
+                + 0.25 * (2.0 * std::f32::consts::PI * 5_000.0 * t).sin()
+        })
+        .collect::<Vec<_>>();
+    let started = Instant::now();
+    for _ in 0..ITERATIONS {
+        let mut filter = WaveformFilter::default();
+        let mut bin_count = 0;
+        for &sample in black_box(&samples) {
+            if let Some(bin) = filter.add_sample(sample) {
+                black_box(bin);
+                bin_count += 1;
+            }
+        }
+        black_box(filter.finish());
+        black_box(bin_count);
+    }
+    let elapsed = started.elapsed();
+    let samples_per_sec = f64::from(ITERATIONS) * samples.len() as f64 / elapsed.as_secs_f64();
+    println!(
+        "{iterations} x {sample_count} samples in {elapsed:?}: {msamples_per_sec:.1} Msamples/sec",
+        iterations = ITERATIONS,
+        sample_count = samples.len(),
+        msamples_per_sec = samples_per_sec / 1_000_000.0,
+    );
+}
@@ -0,0 +1,120 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Detection of activity and silence
+
+use crate::FilteredWaveformBin;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActivityDetectorConfig {
+    /// Minimum `all` energy for becoming active
+    pub on_threshold: f32,
+
+    /// Maximum `all` energy for becoming inactive
+    ///
+    /// Should not exceed [`Self::on_threshold`].
+    pub off_threshold: f32,
+
+    /// Number of consecutive bins below [`Self::off_threshold`]
+    /// that are still considered as active
+    pub hold_bins: usize,
+}
+
+impl ActivityDetectorConfig {
+    pub const DEFAULT: Self = Self {
+        on_threshold: 0.05,
+        off_threshold: 0.02,
+        hold_bins: 15,
+    };
+}
+
+impl Default for ActivityDetectorConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Gate with hysteresis for classifying bins as active or silent
+///
+/// Brief dips of the energy don't toggle the state, i.e. the detector
+/// only becomes inactive after the energy stayed below the off threshold
+/// for longer than the hold time.
+#[derive(Debug)]
+pub struct ActivityDetector {
+    config: ActivityDetectorConfig,
+    active: bool,
+    bins_below_off_threshold: usize,
+}
+
+impl Default for ActivityDetector {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl ActivityDetector {
+    #[must_use]
+    pub fn new(config: ActivityDetectorConfig) -> Self {
+        debug_assert!(config.off_threshold <= config.on_threshold);
+        Self {
+            config,
+            active: false,
+            bins_below_off_threshold: 0,
+        }
+    }
+
+    /// The current state
+    #[must_use]
+    pub const fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Classify the next bin
+    ///
+    /// Returns `true` if the bin is considered as active.
+    pub fn detect(&mut self, bin: &FilteredWaveformBin) -> bool {
+        let ActivityDetectorConfig {
+            on_threshold,
+            off_threshold,
+            hold_bins,
+        } = self.config;
+        let energy = bin.all.energy.to_f32();
+        if !self.active {
+            self.active = energy >= on_threshold;
+        } else if energy < off_threshold {
+            self.bins_below_off_threshold += 1;
+            self.active = self.bins_below_off_threshold <= hold_bins;
+        } else {
+            self.bins_below_off_threshold = 0;
+        }
+        if !self.active {
+            self.bins_below_off_threshold = 0;
+        }
+        self.active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::bin_with_all_energy;
+
+    use super::{ActivityDetector, ActivityDetectorConfig};
+
+    #[test]
+    fn hysteresis_and_hold() {
+        let mut detector = ActivityDetector::new(ActivityDetectorConfig {
+            on_threshold: 0.5,
+            off_threshold: 0.1,
+            hold_bins: 2,
+        });
+        let activity = [0, 100, 200, 100, 0, 0, 100, 0, 0, 0, 100]
+            .into_iter()
+            .map(|energy| detector.detect(&bin_with_all_energy(energy)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            [false, false, true, true, true, true, true, true, true, false, false],
+            activity.as_slice()
+        );
+        assert!(!detector.is_active());
+    }
+}
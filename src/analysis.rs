@@ -0,0 +1,829 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Track-level analysis of bins
+
+use std::num::NonZeroUsize;
+
+use crate::{
+    resample::resample_ranges, waveform::max_band_energy, Band, FilteredWaveformBin, WaveformBin,
+    WaveformVal,
+};
+
+fn bin_duration_secs(samples_per_bin: f32, sample_rate_hz: f32) -> f32 {
+    debug_assert!(samples_per_bin > 0.0);
+    debug_assert!(sample_rate_hz > 0.0);
+    samples_per_bin / sample_rate_hz
+}
+
+/// Time position of the bin with the highest energy
+///
+/// Returns the center of the bin with the highest `all` energy in seconds
+/// or `None` if `bins` is empty. If multiple bins share the highest energy
+/// then the first one is selected.
+#[must_use]
+pub fn peak_energy_position_secs(
+    bins: &[FilteredWaveformBin],
+    samples_per_bin: f32,
+    sample_rate_hz: f32,
+) -> Option<f32> {
+    peak_energy_position_secs_smoothed(bins, NonZeroUsize::MIN, samples_per_bin, sample_rate_hz)
+}
+
+/// Time position of the window of bins with the highest energy
+///
+/// Like [`peak_energy_position_secs()`], but averages the `all` energy
+/// over `window_len` consecutive bins to avoid a bias towards single
+/// transients. Returns the center of the selected window in seconds.
+#[must_use]
+pub fn peak_energy_position_secs_smoothed(
+    bins: &[FilteredWaveformBin],
+    window_len: NonZeroUsize,
+    samples_per_bin: f32,
+    sample_rate_hz: f32,
+) -> Option<f32> {
+    if bins.is_empty() {
+        return None;
+    }
+    let window_len = window_len.get().min(bins.len());
+    let (start, _) = bins
+        .windows(window_len)
+        .map(|window| {
+            window
+                .iter()
+                .map(|bin| bin.all.energy.to_f32())
+                .sum::<f32>()
+        })
+        .enumerate()
+        .fold((0, f32::MIN), |(max_index, max_sum), (index, sum)| {
+            if sum > max_sum {
+                (index, sum)
+            } else {
+                (max_index, max_sum)
+            }
+        });
+    #[allow(clippy::cast_precision_loss)]
+    let center = start as f32 + window_len as f32 / 2.0;
+    Some(center * bin_duration_secs(samples_per_bin, sample_rate_hz))
+}
+
+/// Split bins into segments of a fixed duration
+///
+/// Each bin is assigned to the segment that contains its start time,
+/// i.e. segments are aligned to bin boundaries and the first bin of
+/// segment `k` is the first bin that starts at or after `k * segment_secs`.
+/// The positions are calculated in samples to avoid rounding errors
+/// at the segment boundaries. The last segment might be shorter. Empty
+/// segments, e.g. if the segments are shorter than the bins, are omitted.
+#[must_use]
+pub fn segment_bins(
+    bins: &[FilteredWaveformBin],
+    samples_per_bin: f32,
+    sample_rate_hz: f32,
+    segment_secs: f32,
+) -> Vec<&[FilteredWaveformBin]> {
+    debug_assert!(bin_duration_secs(samples_per_bin, sample_rate_hz) > 0.0);
+    debug_assert!(segment_secs > 0.0);
+    let samples_per_segment = f64::from(segment_secs) * f64::from(sample_rate_hz);
+    #[allow(clippy::cast_precision_loss)]
+    let segment_of_bin =
+        |index: usize| (index as f64 * f64::from(samples_per_bin) / samples_per_segment).floor();
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for index in 1..bins.len() {
+        #[allow(clippy::float_cmp)] // Identical computations
+        if segment_of_bin(index) != segment_of_bin(start) {
+            segments.push(&bins[start..index]);
+            start = index;
+        }
+    }
+    if start < bins.len() {
+        segments.push(&bins[start..]);
+    }
+    segments
+}
+
+/// The bins that overlap a time range
+///
+/// Includes all bins that overlap with `start_secs..end_secs`, i.e. also
+/// partially covered bins at both ends. The range is clamped to the
+/// duration of `bins`. Returns an empty slice if the range is empty or
+/// outside of the bins.
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+pub fn bin_slice(
+    bins: &[FilteredWaveformBin],
+    start_secs: f32,
+    end_secs: f32,
+    samples_per_bin: f32,
+    sample_rate_hz: f32,
+) -> &[FilteredWaveformBin] {
+    debug_assert!(bin_duration_secs(samples_per_bin, sample_rate_hz) > 0.0);
+    let bins_per_sec = sample_rate_hz / samples_per_bin;
+    // Negative and NaN values saturate to 0
+    let start = ((start_secs * bins_per_sec).floor() as usize).min(bins.len());
+    let end = ((end_secs * bins_per_sec).ceil() as usize).min(bins.len());
+    if start >= end {
+        return &[];
+    }
+    &bins[start..end]
+}
+
+/// Join two waveforms with a crossfade
+///
+/// The last `overlap_bins` bins of `a` are mixed with the first
+/// `overlap_bins` bins of `b`, fading from `a` to `b`, see
+/// [`FilteredWaveformBin::mix()`]. The remaining bins are concatenated
+/// unmodified. The overlap is clamped to the length of the shorter
+/// waveform.
+#[must_use]
+#[allow(clippy::cast_precision_loss)]
+pub fn join_with_crossfade(
+    a: &[FilteredWaveformBin],
+    b: &[FilteredWaveformBin],
+    overlap_bins: usize,
+) -> Vec<FilteredWaveformBin> {
+    let overlap = overlap_bins.min(a.len()).min(b.len());
+    let (a_head, a_tail) = a.split_at(a.len() - overlap);
+    let (b_head, b_tail) = b.split_at(overlap);
+    let mut bins = Vec::with_capacity(a.len() + b.len() - overlap);
+    bins.extend_from_slice(a_head);
+    bins.extend(a_tail.iter().zip(b_head).enumerate().map(|(i, (a, b))| {
+        // Exclude both end points that are already covered by a and b
+        let t = (i + 1) as f32 / (overlap + 1) as f32;
+        a.mix(b, t)
+    }));
+    bins.extend_from_slice(b_tail);
+    bins
+}
+
+/// Attack and decay times of the `all` energy after each onset
+///
+/// The onsets are given as strictly increasing bin indices, e.g. detected
+/// by a [`PunchTracker`](crate::PunchTracker). Each event extends from its
+/// onset to the next onset or the end of `bins`. The attack time is the
+/// duration from the onset to the bin with the highest energy of the event.
+/// The decay time is the duration from this peak to the first bin with at
+/// most half of the peak energy. If the energy does not decay to half
+/// within the event, the decay time extends to the end of the event, i.e.
+/// it is a lower bound. Both times are returned in milliseconds and are
+/// multiples of the bin duration.
+///
+/// Events that would be empty are skipped, i.e. onsets beyond the end
+/// of `bins` and onsets that are not less than the next onset. Unsorted
+/// or duplicate onsets thus result in fewer events.
+///
+/// Short attack and decay times are characteristic for percussive
+/// events, long times for sustained events.
+#[must_use]
+pub fn onset_envelope_timings_ms(
+    bins: &[FilteredWaveformBin],
+    onsets: &[usize],
+    samples_per_bin: f32,
+    sample_rate_hz: f32,
+) -> Vec<(f32, f32)> {
+    let bin_duration_ms = bin_duration_secs(samples_per_bin, sample_rate_hz) * 1000.0;
+    #[allow(clippy::cast_precision_loss)]
+    let to_ms = |bin_count: usize| bin_count as f32 * bin_duration_ms;
+    onsets
+        .iter()
+        .enumerate()
+        .filter_map(|(index, &onset)| {
+            let end = onsets
+                .get(index + 1)
+                .copied()
+                .unwrap_or(bins.len())
+                .min(bins.len());
+            bins.get(onset..end).filter(|event| !event.is_empty())
+        })
+        .map(|event| {
+            let (peak_index, peak_energy) =
+                event
+                    .iter()
+                    .enumerate()
+                    .fold((0, 0.0), |(max_index, max_energy), (index, bin)| {
+                        let energy = bin.all.energy.to_f32();
+                        if energy > max_energy {
+                            (index, energy)
+                        } else {
+                            (max_index, max_energy)
+                        }
+                    });
+            let decay_len = event[peak_index..]
+                .iter()
+                .position(|bin| bin.all.energy.to_f32() <= peak_energy / 2.0)
+                .unwrap_or(event.len() - peak_index);
+            (to_ms(peak_index), to_ms(decay_len))
+        })
+        .collect()
+}
+
+/// Level threshold for excluding silent bins from [`loudness_range_db()`]
+pub const LOUDNESS_RANGE_SILENCE_THRESHOLD_DB: f32 = -70.0;
+
+/// Lower percentile of [`loudness_range_db()`]
+const LOUDNESS_RANGE_LOW_PERCENTILE: f32 = 0.1;
+
+/// Upper percentile of [`loudness_range_db()`]
+const LOUDNESS_RANGE_HIGH_PERCENTILE: f32 = 0.95;
+
+/// Nearest-rank percentile of sorted values
+///
+/// The percentile `p` is given as a fraction in the range `0..=1`.
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+    debug_assert!(!sorted.is_empty());
+    debug_assert!((0.0..=1.0).contains(&p));
+    #[allow(clippy::cast_precision_loss)]
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    let index = ((sorted.len() - 1) as f32 * p).round() as usize;
+    sorted[index]
+}
+
+/// Loudness range in decibels
+///
+/// The difference between the 95th and the 10th percentile of the `all`
+/// energy in dB, similar to the EBU R128 loudness range (LRA). Bins below
+/// [`LOUDNESS_RANGE_SILENCE_THRESHOLD_DB`] are excluded.
+///
+/// Returns `0.0` if no bins remain. Lower values indicate a more
+/// compressed track.
+#[must_use]
+pub fn loudness_range_db(bins: &[FilteredWaveformBin]) -> f32 {
+    let mut levels_db = bins
+        .iter()
+        .map(|bin| bin.all.energy.to_db())
+        .filter(|&level_db| level_db >= LOUDNESS_RANGE_SILENCE_THRESHOLD_DB)
+        .collect::<Vec<_>>();
+    if levels_db.is_empty() {
+        return 0.0;
+    }
+    levels_db.sort_unstable_by(f32::total_cmp);
+    percentile(&levels_db, LOUDNESS_RANGE_HIGH_PERCENTILE)
+        - percentile(&levels_db, LOUDNESS_RANGE_LOW_PERCENTILE)
+}
+
+/// Fraction of clipped bins
+///
+/// Counts the bins with a clipped `all` peak, see [`WaveformBin::is_clipped()`].
+/// Note that the ratio refers to bins, not samples. A single clipped
+/// sample is sufficient for the whole bin to be clipped.
+///
+/// Returns `0.0` if `bins` is empty.
+#[must_use]
+pub fn clipping_ratio(bins: &[FilteredWaveformBin]) -> f32 {
+    clipping_ratio_above(bins, WaveformBin::DEFAULT_CLIPPING_THRESHOLD)
+}
+
+/// Fraction of bins with an `all` peak that reached a threshold
+///
+/// Like [`clipping_ratio()`], but with a custom threshold, see
+/// [`WaveformBin::is_clipped_above()`].
+#[must_use]
+pub fn clipping_ratio_above(bins: &[FilteredWaveformBin], threshold: f32) -> f32 {
+    if bins.is_empty() {
+        return 0.0;
+    }
+    let clipped_count = bins
+        .iter()
+        .filter(|bin| bin.all.is_clipped_above(threshold))
+        .count();
+    #[allow(clippy::cast_precision_loss)]
+    let ratio = clipped_count as f32 / bins.len() as f32;
+    ratio
+}
+
+/// Range of bins that are not silent
+///
+/// Returns the inclusive index range from the first to the last bin with
+/// an `all` peak exceeding `threshold`, or `None` if no such bin exists.
+/// The threshold `WaveformVal(0)` only excludes bins of digital silence,
+/// see [`FilteredWaveformBin::is_silent()`]. Noise and dither typically
+/// get quantized into the lowest buckets and require a slightly higher
+/// threshold for trimming silence.
+#[must_use]
+pub fn active_range(
+    bins: &[FilteredWaveformBin],
+    threshold: WaveformVal,
+) -> Option<(usize, usize)> {
+    let is_active = |bin: &FilteredWaveformBin| bin.all.peak > threshold;
+    let first = bins.iter().position(is_active)?;
+    let last = bins.iter().rposition(is_active)?;
+    Some((first, last))
+}
+
+/// Consecutive bins with the same dominant band
+///
+/// Returns the dominant band of each run and the number of bins, see
+/// [`FilteredWaveformBin::dominant_band()`]. Silent bins without any
+/// dominant band form separate runs.
+#[must_use]
+pub fn dominant_band_runs(bins: &[FilteredWaveformBin]) -> Vec<(Option<Band>, usize)> {
+    let mut runs: Vec<(Option<Band>, usize)> = Vec::new();
+    for band in bins.iter().map(FilteredWaveformBin::dominant_band) {
+        match runs.last_mut() {
+            Some((last_band, len)) if *last_band == band => {
+                *len += 1;
+            }
+            _ => {
+                runs.push((band, 1));
+            }
+        }
+    }
+    runs
+}
+
+/// Fractions of non-silent bins by dominant band
+///
+/// Returns the fractions for the low, mid, and high band in this order,
+/// see [`FilteredWaveformBin::dominant_band()`]. Bins without any
+/// dominant band are ignored. The fractions sum up to `1.0` unless all
+/// bins are silent, in which case all fractions are zero.
+#[must_use]
+pub fn dominant_band_distribution(bins: &[FilteredWaveformBin]) -> [f32; 3] {
+    let mut counts = [0usize; 3];
+    for band in bins.iter().filter_map(FilteredWaveformBin::dominant_band) {
+        let index = match band {
+            Band::Low => 0,
+            Band::Mid => 1,
+            Band::High => 2,
+        };
+        counts[index] += 1;
+    }
+    let total = counts.iter().sum::<usize>();
+    if total == 0 {
+        return [0.0; 3];
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_precision_loss)]
+    counts.map(|count| (count as f64 / total as f64) as f32)
+}
+
+/// The band with the highest energy summed over all bins
+///
+/// Returns `None` if all bins are silent. Ties are resolved in favor of
+/// the lower band, see [`FilteredWaveformBin::dominant_band()`].
+#[must_use]
+pub fn dominant_band_overall(bins: &[FilteredWaveformBin]) -> Option<Band> {
+    let [low, mid, high] = bins.iter().fold([0u64; 3], |[low, mid, high], bin| {
+        [
+            low + u64::from(bin.low.energy.0),
+            mid + u64::from(bin.mid.energy.0),
+            high + u64::from(bin.high.energy.0),
+        ]
+    });
+    let (band, energy) = max_band_energy(low, mid, high);
+    (energy > 0).then_some(band)
+}
+
+/// Spectral centroid of a whole track
+///
+/// The mean of [`FilteredWaveformBin::spectral_centroid()`] weighted by
+/// the `all` energy of each bin, i.e. loud sections count more. Returns
+/// the neutral value `0.5` if all bins are silent.
+#[must_use]
+pub fn track_spectral_centroid(bins: &[FilteredWaveformBin]) -> f32 {
+    let (weighted_sum, weight_sum) =
+        bins.iter()
+            .fold((0.0, 0.0), |(weighted_sum, weight_sum), bin| {
+                let weight = f64::from(bin.all.energy.to_f32());
+                (
+                    weighted_sum + weight * f64::from(bin.spectral_centroid()),
+                    weight_sum + weight,
+                )
+            });
+    if weight_sum == 0.0 {
+        return 0.5;
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let centroid = (weighted_sum / weight_sum) as f32;
+    centroid
+}
+
+/// Lower bound of [`suggest_display_gain()`]
+const MIN_DISPLAY_GAIN: f32 = 1.0 / 16.0;
+
+/// Upper bound of [`suggest_display_gain()`]
+const MAX_DISPLAY_GAIN: f32 = 16.0;
+
+/// Gain for displaying bins with a target brightness
+///
+/// Returns the factor that brings the mean `all` energy of the bins to
+/// `target_mean_energy`, ignoring any clipping. The gain is clamped to
+/// the range `1/16..=16` to not amplify noise or near silence. Returns
+/// `1.0` if the mean energy is zero, e.g. if `bins` is empty.
+#[must_use]
+pub fn suggest_display_gain(bins: &[FilteredWaveformBin], target_mean_energy: f32) -> f32 {
+    debug_assert!(target_mean_energy >= 0.0);
+    if bins.is_empty() {
+        return 1.0;
+    }
+    let energy_sum = bins
+        .iter()
+        .map(|bin| f64::from(bin.all.energy.to_f32()))
+        .sum::<f64>();
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_precision_loss)]
+    let mean_energy = (energy_sum / bins.len() as f64) as f32;
+    if mean_energy == 0.0 {
+        return 1.0;
+    }
+    (target_mean_energy / mean_energy).clamp(MIN_DISPLAY_GAIN, MAX_DISPLAY_GAIN)
+}
+
+/// Crest factor of a whole track
+///
+/// The ratio between the maximum `all` peak and the RMS of all `all`
+/// energies, see [`FilteredWaveformBin::combine()`]. Heavily compressed
+/// masters have a lower crest factor than dynamic recordings. With the
+/// default energy scale the crest factor of a sine wave is `1.0` and not
+/// `SQRT_2`.
+///
+/// Returns `0.0` if all bins are silent.
+#[must_use]
+pub fn track_crest_factor(bins: &[FilteredWaveformBin]) -> f32 {
+    let WaveformBin { peak, energy } = FilteredWaveformBin::combine(bins).all;
+    if energy.is_zero() {
+        return 0.0;
+    }
+    peak.to_f32() / energy.to_f32()
+}
+
+/// Similarity of two waveforms
+///
+/// The longer waveform is resampled to the length of the shorter one
+/// by averaging the `all` energy of adjacent bins. The score is the
+/// Pearson correlation coefficient of the resulting energy envelopes,
+/// i.e. the cosine similarity after subtracting their means. It is in
+/// the range `-1..=1` with `1.0` for envelopes that only differ by a
+/// constant gain and offset, around `0.0` for unrelated envelopes, and
+/// `-1.0` for inverted envelopes.
+///
+/// Returns `0.0` if any of the waveforms is empty or its envelope is
+/// constant, e.g. silent.
+#[must_use]
+pub fn waveform_similarity(lhs: &[FilteredWaveformBin], rhs: &[FilteredWaveformBin]) -> f32 {
+    let len = lhs.len().min(rhs.len());
+    let envelope = |bins: &[FilteredWaveformBin]| {
+        resample_ranges(bins.len(), len)
+            .map(|range| {
+                let range_len = range.len();
+                let energy_sum = bins[range]
+                    .iter()
+                    .map(|bin| f64::from(bin.all.energy.to_f32()))
+                    .sum::<f64>();
+                #[allow(clippy::cast_precision_loss)]
+                let mean_energy = energy_sum / range_len as f64;
+                mean_energy
+            })
+            .collect::<Vec<_>>()
+    };
+    let centered = |envelope: Vec<f64>| {
+        #[allow(clippy::cast_precision_loss)]
+        let mean = envelope.iter().sum::<f64>() / envelope.len().max(1) as f64;
+        envelope.into_iter().map(move |val| val - mean)
+    };
+    let lhs = centered(envelope(lhs)).collect::<Vec<_>>();
+    let rhs = centered(envelope(rhs)).collect::<Vec<_>>();
+    let dot = lhs
+        .iter()
+        .zip(&rhs)
+        .map(|(lhs, rhs)| lhs * rhs)
+        .sum::<f64>();
+    let lhs_norm = lhs.iter().map(|val| val * val).sum::<f64>().sqrt();
+    let rhs_norm = rhs.iter().map(|val| val * val).sum::<f64>().sqrt();
+    if lhs_norm == 0.0 || rhs_norm == 0.0 {
+        return 0.0;
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let similarity = (dot / (lhs_norm * rhs_norm)).clamp(-1.0, 1.0) as f32;
+    similarity
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use crate::{
+        test_util::bin_with_all_energy, Band, FilteredWaveformBin, WaveformBin, WaveformVal,
+    };
+
+    use super::{
+        active_range, bin_slice, clipping_ratio, clipping_ratio_above, dominant_band_distribution,
+        dominant_band_overall, dominant_band_runs, join_with_crossfade, loudness_range_db,
+        onset_envelope_timings_ms, peak_energy_position_secs, peak_energy_position_secs_smoothed,
+        segment_bins, suggest_display_gain, track_crest_factor, track_spectral_centroid,
+        waveform_similarity,
+    };
+
+    fn bin_with_band_energy(band: Band, energy: u8) -> FilteredWaveformBin {
+        let mut bin = FilteredWaveformBin::default();
+        let band_bin = match band {
+            Band::Low => &mut bin.low,
+            Band::Mid => &mut bin.mid,
+            Band::High => &mut bin.high,
+        };
+        band_bin.energy = WaveformVal(energy);
+        bin
+    }
+
+    #[test]
+    fn peak_energy_position() {
+        assert!(peak_energy_position_secs(&[], 100.0, 1000.0).is_none());
+        let bins = [10, 200, 10, 150, 150, 150, 10]
+            .into_iter()
+            .map(bin_with_all_energy)
+            .collect::<Vec<_>>();
+        // 10 bins per second
+        let secs = peak_energy_position_secs(&bins, 100.0, 1000.0).unwrap();
+        assert!((secs - 0.15).abs() < 1e-6);
+        let secs =
+            peak_energy_position_secs_smoothed(&bins, NonZeroUsize::new(3).unwrap(), 100.0, 1000.0)
+                .unwrap();
+        assert!((secs - 0.45).abs() < 1e-6);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn loudness_range() {
+        assert_eq!(0.0, loudness_range_db(&[]));
+        let bins = [0, 0, 255, 255, 0]
+            .into_iter()
+            .map(bin_with_all_energy)
+            .collect::<Vec<_>>();
+        assert_eq!(0.0, loudness_range_db(&bins));
+        // -6 dB vs. 0 dB, silent bins excluded
+        let bins = [0, 128, 128, 128, 255, 255, 255, 0]
+            .into_iter()
+            .map(bin_with_all_energy)
+            .collect::<Vec<_>>();
+        let lra = loudness_range_db(&bins);
+        assert!((lra - 5.99).abs() < 0.01);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn clipping() {
+        assert_eq!(0.0, clipping_ratio(&[]));
+        let bins = [254, 255, 0, 255]
+            .into_iter()
+            .map(bin_with_all_energy)
+            .collect::<Vec<_>>();
+        assert_eq!(0.5, clipping_ratio(&bins));
+        assert_eq!(0.75, clipping_ratio_above(&bins, 0.99));
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn display_gain() {
+        assert_eq!(1.0, suggest_display_gain(&[], 0.5));
+        assert_eq!(1.0, suggest_display_gain(&[bin_with_all_energy(0)], 0.5));
+        let bins = [51, 0, 102]
+            .into_iter()
+            .map(bin_with_all_energy)
+            .collect::<Vec<_>>();
+        // Mean energy: 0.2
+        assert!((suggest_display_gain(&bins, 0.5) - 2.5).abs() < 1e-6);
+        assert_eq!(16.0, suggest_display_gain(&[bin_with_all_energy(1)], 1.0));
+        assert_eq!(1.0 / 16.0, suggest_display_gain(&bins, 0.0));
+    }
+
+    #[test]
+    fn active_bins() {
+        assert!(active_range(&[], WaveformVal(0)).is_none());
+        let bins = [0, 2, 0, 100, 0, 50, 1, 0]
+            .into_iter()
+            .map(bin_with_all_energy)
+            .collect::<Vec<_>>();
+        assert_eq!(Some((1, 6)), active_range(&bins, WaveformVal(0)));
+        assert_eq!(Some((3, 5)), active_range(&bins, WaveformVal(2)));
+        assert_eq!(Some((3, 3)), active_range(&bins, WaveformVal(50)));
+        assert!(active_range(&bins, WaveformVal(100)).is_none());
+    }
+
+    #[test]
+    fn dominant_band_run_lengths() {
+        assert!(dominant_band_runs(&[]).is_empty());
+        let bins = [
+            bin_with_band_energy(Band::Low, 10),
+            bin_with_band_energy(Band::Low, 20),
+            bin_with_band_energy(Band::Low, 0),
+            bin_with_band_energy(Band::High, 0),
+            bin_with_band_energy(Band::High, 5),
+            bin_with_band_energy(Band::Mid, 5),
+            bin_with_band_energy(Band::Mid, 5),
+        ];
+        assert_eq!(
+            vec![
+                (Some(Band::Low), 2),
+                (None, 2),
+                (Some(Band::High), 1),
+                (Some(Band::Mid), 2)
+            ],
+            dominant_band_runs(&bins)
+        );
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn dominant_band_fractions() {
+        assert_eq!([0.0; 3], dominant_band_distribution(&[]));
+        let bins = [
+            bin_with_band_energy(Band::Low, 10),
+            bin_with_band_energy(Band::Low, 20),
+            bin_with_band_energy(Band::Low, 0),
+            bin_with_band_energy(Band::Low, 5),
+            bin_with_band_energy(Band::High, 5),
+        ];
+        assert_eq!([0.75, 0.0, 0.25], dominant_band_distribution(&bins));
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn track_centroid() {
+        assert_eq!(0.5, track_spectral_centroid(&[]));
+        let bin = |all, high| FilteredWaveformBin {
+            all: WaveformBin {
+                peak: WaveformVal(all),
+                energy: WaveformVal(all),
+            },
+            low: WaveformBin {
+                peak: WaveformVal(100 - high),
+                energy: WaveformVal(100 - high),
+            },
+            high: WaveformBin {
+                peak: WaveformVal(high),
+                energy: WaveformVal(high),
+            },
+            ..Default::default()
+        };
+        assert_eq!(0.5, track_spectral_centroid(&[bin(0, 100)]));
+        // Loud and bright vs. quiet and dark
+        let centroid = track_spectral_centroid(&[bin(204, 100), bin(51, 0)]);
+        assert!((centroid - 0.8).abs() < 1e-6);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn similarity() {
+        let bins = [10, 200, 10, 150, 150, 150, 10]
+            .into_iter()
+            .map(bin_with_all_energy)
+            .collect::<Vec<_>>();
+        assert_eq!(0.0, waveform_similarity(&bins, &[]));
+        assert_eq!(0.0, waveform_similarity(&bins, &[bin_with_all_energy(0)]));
+        assert!((waveform_similarity(&bins, &bins) - 1.0).abs() < 1e-6);
+        // Same envelope at twice the resolution
+        let doubled = bins
+            .iter()
+            .flat_map(|bin| [bin.clone(), bin.clone()])
+            .collect::<Vec<_>>();
+        assert!((waveform_similarity(&bins, &doubled) - 1.0).abs() < 1e-6);
+        assert!((waveform_similarity(&doubled, &bins) - 1.0).abs() < 1e-6);
+        let reversed = bins.iter().rev().cloned().collect::<Vec<_>>();
+        let similarity = waveform_similarity(&bins, &reversed);
+        assert!(similarity < 0.5);
+        assert!(similarity > -1.0);
+        // Independent of a constant offset
+        let louder = bins
+            .iter()
+            .map(|bin| bin_with_all_energy(bin.all.energy.0 + 50))
+            .collect::<Vec<_>>();
+        assert!((waveform_similarity(&bins, &louder) - 1.0).abs() < 1e-6);
+        // Anti-correlated
+        let inverted = bins
+            .iter()
+            .map(|bin| bin_with_all_energy(u8::MAX - bin.all.energy.0))
+            .collect::<Vec<_>>();
+        assert!((waveform_similarity(&bins, &inverted) + 1.0).abs() < 1e-6);
+        // Unrelated, but both non-silent
+        let alternating = [100, 200, 100, 200].map(bin_with_all_energy);
+        let ramp = [100, 100, 200, 200].map(bin_with_all_energy);
+        assert!(waveform_similarity(&alternating, &ramp).abs() < 1e-6);
+        // Constant envelopes have no shape
+        let constant = [150; 7].map(bin_with_all_energy);
+        assert_eq!(0.0, waveform_similarity(&bins, &constant));
+    }
+
+    #[test]
+    fn segments() {
+        let bins = vec![FilteredWaveformBin::default(); 2_501];
+        assert!(segment_bins(&[], 441.0, 44_100.0, 10.0).is_empty());
+        // 100 bins per second
+        let segment_lens = segment_bins(&bins, 441.0, 44_100.0, 10.0)
+            .into_iter()
+            .map(<[_]>::len)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![1_000, 1_000, 501], segment_lens);
+        // Segments that are not aligned with bins
+        let segment_lens = segment_bins(&bins[..10], 441.0, 44_100.0, 0.031_25)
+            .into_iter()
+            .map(<[_]>::len)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![4, 3, 3], segment_lens);
+        // Segments that are shorter than bins
+        assert_eq!(3, segment_bins(&bins[..3], 441.0, 44_100.0, 0.001).len());
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn crest_factor() {
+        assert_eq!(0.0, track_crest_factor(&[]));
+        assert_eq!(0.0, track_crest_factor(&[bin_with_all_energy(0)]));
+        assert_eq!(1.0, track_crest_factor(&vec![bin_with_all_energy(200); 3]));
+        let bin = |peak, energy| FilteredWaveformBin {
+            all: WaveformBin {
+                peak: WaveformVal(peak),
+                energy: WaveformVal(energy),
+            },
+            ..Default::default()
+        };
+        let crest_factor = track_crest_factor(&[bin(255, 51), bin(51, 51), bin(0, 0), bin(0, 0)]);
+        // RMS of the energies: 0.2 / sqrt(2)
+        assert!((crest_factor - 5.0 * std::f32::consts::SQRT_2).abs() < 0.1);
+    }
+
+    #[test]
+    fn dominant_band_over_all_bins() {
+        assert!(dominant_band_overall(&[]).is_none());
+        assert!(dominant_band_overall(&[FilteredWaveformBin::default()]).is_none());
+        // More bins are dominated by the high band, but the low band
+        // has the highest total energy.
+        let bins = [
+            bin_with_band_energy(Band::High, 50),
+            bin_with_band_energy(Band::High, 50),
+            bin_with_band_energy(Band::Low, 200),
+            bin_with_band_energy(Band::Mid, 20),
+        ];
+        assert_eq!(Some(Band::Low), dominant_band_overall(&bins));
+        assert_eq!(
+            Some(Band::Mid),
+            dominant_band_overall(&[
+                bin_with_band_energy(Band::Mid, 1),
+                bin_with_band_energy(Band::High, 1),
+            ])
+        );
+    }
+
+    #[test]
+    fn bin_slice_is_clamped() {
+        let bins = (0..10u8).map(bin_with_all_energy).collect::<Vec<_>>();
+        // 10 bins per second
+        let slice = |start_secs, end_secs| {
+            bin_slice(&bins, start_secs, end_secs, 100.0, 1000.0)
+                .iter()
+                .map(|bin| bin.all.energy.0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![2, 3, 4], slice(0.2, 0.5));
+        // Partially covered bins
+        assert_eq!(vec![2, 3, 4], slice(0.25, 0.45));
+        assert_eq!(vec![8, 9], slice(0.8, 100.0));
+        assert_eq!(vec![0, 1], slice(-1.0, 0.2));
+        assert!(slice(0.5, 0.5).is_empty());
+        assert!(slice(0.6, 0.4).is_empty());
+        assert!(slice(2.0, 3.0).is_empty());
+        assert!(slice(f32::NAN, 0.0).is_empty());
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn envelope_timings_after_onsets() {
+        // 10 ms per bin
+        let bins = [0, 200, 100, 50, 10, 50, 100, 150, 200, 200]
+            .into_iter()
+            .map(bin_with_all_energy)
+            .collect::<Vec<_>>();
+        let timings = onset_envelope_timings_ms(&bins, &[1, 4, 20], 10.0, 1000.0);
+        assert_eq!(2, timings.len());
+        // Percussive: Immediate peak and decay to half after 1 bin
+        assert_eq!((0.0, 10.0), timings[0]);
+        // Sustained: Slow attack without decay
+        assert_eq!((40.0, 20.0), timings[1]);
+        assert!(onset_envelope_timings_ms(&bins, &[], 10.0, 1000.0).is_empty());
+        // Empty events of unsorted and duplicate onsets are skipped
+        assert_eq!(
+            timings,
+            onset_envelope_timings_ms(&bins, &[4, 1, 1, 4, 20], 10.0, 1000.0)
+        );
+    }
+
+    #[test]
+    fn join_with_crossfade_overlap() {
+        let a = [200; 4].map(bin_with_all_energy);
+        let b = [100; 3].map(bin_with_all_energy);
+        let joined = join_with_crossfade(&a, &b, 2);
+        assert_eq!(5, joined.len());
+        assert_eq!(WaveformVal(200), joined[1].all.energy);
+        assert_eq!(WaveformVal(100), joined[4].all.energy);
+        // Fading from a to b
+        assert!(joined[2].all.energy > joined[3].all.energy);
+        assert!(joined[2].all.energy < WaveformVal(200));
+        assert!(joined[3].all.energy > WaveformVal(100));
+        // The overlap is clamped to the shorter waveform
+        assert_eq!(4, join_with_crossfade(&a, &b, 10).len());
+        assert_eq!(7, join_with_crossfade(&a, &b, 0).len());
+        assert_eq!(3, join_with_crossfade(&[], &b, 2).len());
+    }
+}
@@ -0,0 +1,181 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! On-demand computation of bins
+
+use std::{collections::HashMap, num::NonZeroUsize, ops::Range};
+
+use crate::{FilteredWaveformBin, WaveformFilter, WaveformFilterConfig};
+
+/// Lazily computed and memoized bins of a track
+///
+/// The bins are computed in chunks of a fixed number of bins when
+/// requested for the first time, e.g. while scrubbing backwards through
+/// a long track. The filters are settled by processing up to
+/// `warmup_samples` samples preceding each chunk, see
+/// [`compute_waveform_range()`](crate::compute_waveform_range).
+///
+/// The bin boundaries are the same as when computing all bins at once.
+/// [`WaveformFilterConfig::discard_warmup_bins`] is ignored.
+#[derive(Debug)]
+pub struct WaveformCache<'a> {
+    config: WaveformFilterConfig,
+    samples: &'a [f32],
+    warmup_samples: usize,
+    chunk_bins: NonZeroUsize,
+    bin_count: usize,
+    chunks: HashMap<usize, Vec<FilteredWaveformBin>>,
+}
+
+impl<'a> WaveformCache<'a> {
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn new(
+        mut config: WaveformFilterConfig,
+        samples: &'a [f32],
+        warmup_samples: usize,
+        chunk_bins: NonZeroUsize,
+    ) -> Self {
+        config.discard_warmup_bins = 0;
+        let filter = WaveformFilter::new(config.clone());
+        let bin_count = if samples.is_empty() {
+            0
+        } else {
+            // Including the trailing bin
+            filter.bin_count_for_samples(samples.len()) + 1
+        };
+        Self {
+            config,
+            samples,
+            warmup_samples,
+            chunk_bins,
+            bin_count,
+            chunks: HashMap::new(),
+        }
+    }
+
+    /// Total number of bins
+    #[must_use]
+    pub const fn bin_count(&self) -> usize {
+        self.bin_count
+    }
+
+    /// Number of bins that have already been computed
+    #[must_use]
+    pub fn cached_bin_count(&self) -> usize {
+        self.chunks.values().map(Vec::len).sum()
+    }
+
+    /// Discard all computed bins
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    /// Get a range of bins
+    ///
+    /// Computes all missing chunks that overlap with `range`. The range
+    /// is truncated to [`Self::bin_count()`].
+    pub fn bins(&mut self, range: Range<usize>) -> Vec<FilteredWaveformBin> {
+        let end = range.end.min(self.bin_count);
+        let start = range.start.min(end);
+        let chunk_bins = self.chunk_bins.get();
+        let mut bins = Vec::with_capacity(end - start);
+        let mut index = start;
+        while index < end {
+            let chunk_index = index / chunk_bins;
+            let chunk_start = chunk_index * chunk_bins;
+            let chunk = self.chunks.entry(chunk_index).or_insert_with(|| {
+                compute_chunk(
+                    &self.config,
+                    self.samples,
+                    self.warmup_samples,
+                    chunk_start,
+                    chunk_bins,
+                )
+            });
+            let chunk_end = (chunk_start + chunk.len()).min(end);
+            bins.extend_from_slice(&chunk[index - chunk_start..chunk_end - chunk_start]);
+            index = chunk_end;
+        }
+        bins
+    }
+}
+
+/// Index of the first sample of a bin
+///
+/// Bins start at the first sample at or after the fractional boundary.
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_precision_loss)]
+#[allow(clippy::cast_sign_loss)]
+fn bin_start_sample(bin_index: usize, samples_per_bin: f32) -> (usize, f32) {
+    let boundary = bin_index as f64 * f64::from(samples_per_bin);
+    let start = boundary.ceil();
+    // The fraction of the first sample that belongs to the preceding bin
+    (start as usize, (start - boundary) as f32)
+}
+
+fn compute_chunk(
+    config: &WaveformFilterConfig,
+    samples: &[f32],
+    warmup_samples: usize,
+    first_bin: usize,
+    chunk_bins: usize,
+) -> Vec<FilteredWaveformBin> {
+    let samples_per_bin = config.samples_per_bin();
+    let (start, start_offset) = bin_start_sample(first_bin, samples_per_bin);
+    let (end, _) = bin_start_sample(first_bin + chunk_bins, samples_per_bin);
+    let start = start.min(samples.len());
+    let end = end.min(samples.len());
+    let mut filter = WaveformFilter::new(config.clone());
+    filter.warm_up(&samples[start.saturating_sub(warmup_samples)..start]);
+    if start_offset > 0.0 {
+        filter.shorten_first_bin(start_offset);
+    }
+    let mut bins = Vec::with_capacity(chunk_bins);
+    filter.process_into(&samples[start..end], &mut bins);
+    filter.finish_into(&mut bins);
+    bins
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use crate::{compute_waveform, WaveformFilterConfig};
+
+    use super::WaveformCache;
+
+    #[test]
+    fn scrub_backward() {
+        let config = WaveformFilterConfig::DEFAULT;
+        let samples = (0..100_000)
+            .map(|i| if i % 100 < 50 { 0.5 } else { -0.5 })
+            .collect::<Vec<_>>();
+        let all_bins = compute_waveform(config.clone(), &samples);
+        let mut cache =
+            WaveformCache::new(config, &samples, 10_000, NonZeroUsize::new(16).unwrap());
+        assert_eq!(all_bins.len(), cache.bin_count());
+        assert_eq!(0, cache.cached_bin_count());
+        let len = all_bins.len();
+        let bins = cache.bins(len - 20..len + 10);
+        assert_eq!(20, bins.len());
+        let cached_bin_count = cache.cached_bin_count();
+        assert!(cached_bin_count >= 20);
+        for (start, end) in [(len - 30, len - 15), (10, 40), (0, 20), (5, 45)] {
+            let bins = cache.bins(start..end);
+            assert_eq!(end - start, bins.len());
+            for (expected, actual) in all_bins[start..end].iter().zip(&bins) {
+                assert_eq!(expected.all.peak, actual.all.peak);
+                assert_eq!(expected.all.energy, actual.all.energy);
+                assert_eq!(expected.low.energy, actual.low.energy);
+            }
+        }
+        // Repeated requests are served from the cache
+        let cached_bin_count = cache.cached_bin_count();
+        assert_eq!(40, cache.bins(5..45).len());
+        assert_eq!(cached_bin_count, cache.cached_bin_count());
+        assert!(cached_bin_count < len);
+        cache.clear();
+        assert_eq!(0, cache.cached_bin_count());
+    }
+}
@@ -0,0 +1,185 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Color space conversions
+//!
+//! RGB colors are linear sRGB, i.e. without gamma encoding, unless
+//! explicitly typed as [`SrgbRgb`]. CIE L*a*b* colors are relative to
+//! the D65 white point with the lightness L* in the range `0..=100`.
+
+/// Linear sRGB color without gamma encoding
+///
+/// Components are in the range `0..=1`. Colors should be blended
+/// in this color space.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinearRgb(pub [f32; 3]);
+
+/// Gamma encoded sRGB color
+///
+/// Components are in the range `0..=1`. Suitable for display, but
+/// not for blending.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SrgbRgb(pub [f32; 3]);
+
+/// <https://en.wikipedia.org/wiki/SRGB#Transfer_function_(%22gamma%22)>
+fn srgb_encode(val: f32) -> f32 {
+    if val <= 0.003_130_8 {
+        12.92 * val
+    } else {
+        1.055 * val.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn srgb_decode(val: f32) -> f32 {
+    if val <= 0.040_45 {
+        val / 12.92
+    } else {
+        ((val + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+impl LinearRgb {
+    /// Apply the sRGB gamma encoding
+    #[must_use]
+    pub fn to_srgb(self) -> SrgbRgb {
+        let Self(rgb) = self;
+        SrgbRgb(rgb.map(srgb_encode))
+    }
+
+    /// Relative luminance with Rec. 709 weights
+    ///
+    /// Identical to the Y component in CIE XYZ, i.e. a grayscale value
+    /// in the range `0..=1` that is still linear.
+    #[must_use]
+    pub fn luminance(self) -> f32 {
+        let Self([red, green, blue]) = self;
+        0.212_672_9 * red + 0.715_152_2 * green + 0.072_175 * blue
+    }
+}
+
+impl SrgbRgb {
+    /// Remove the sRGB gamma encoding
+    #[must_use]
+    pub fn to_linear(self) -> LinearRgb {
+        let Self(rgb) = self;
+        LinearRgb(rgb.map(srgb_decode))
+    }
+
+    /// Quantize into 8-bit components
+    #[must_use]
+    pub fn to_rgb8(self) -> [u8; 3] {
+        let Self(rgb) = self;
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        rgb.map(|val| (val.clamp(0.0, 1.0) * f32::from(u8::MAX)).round() as u8)
+    }
+}
+
+// D65 reference white
+const WHITE_X: f32 = 0.950_47;
+const WHITE_Y: f32 = 1.0;
+const WHITE_Z: f32 = 1.088_83;
+
+const LAB_DELTA: f32 = 6.0 / 29.0;
+
+fn lab_f(t: f32) -> f32 {
+    if t > LAB_DELTA * LAB_DELTA * LAB_DELTA {
+        t.cbrt()
+    } else {
+        t / (3.0 * LAB_DELTA * LAB_DELTA) + 4.0 / 29.0
+    }
+}
+
+fn lab_f_inv(t: f32) -> f32 {
+    if t > LAB_DELTA {
+        t * t * t
+    } else {
+        3.0 * LAB_DELTA * LAB_DELTA * (t - 4.0 / 29.0)
+    }
+}
+
+/// Convert a linear sRGB color into CIE L*a*b*
+#[must_use]
+#[allow(clippy::many_single_char_names)] // Standard notation
+pub fn linear_rgb_to_lab(rgb: LinearRgb) -> (f32, f32, f32) {
+    let LinearRgb([red, green, blue]) = rgb;
+    let x = 0.412_456_4 * red + 0.357_576_1 * green + 0.180_437_5 * blue;
+    let y = rgb.luminance();
+    let z = 0.019_333_9 * red + 0.119_192 * green + 0.950_304_1 * blue;
+    let fx = lab_f(x / WHITE_X);
+    let fy = lab_f(y / WHITE_Y);
+    let fz = lab_f(z / WHITE_Z);
+    let lightness = 116.0 * fy - 16.0;
+    let a = 500.0 * (fx - fy);
+    let b = 200.0 * (fy - fz);
+    (lightness, a, b)
+}
+
+/// Convert a CIE L*a*b* color into linear sRGB
+///
+/// The components of the resulting color are clamped to the range `0..=1`.
+#[must_use]
+#[allow(clippy::many_single_char_names)] // Standard notation
+pub fn lab_to_linear_rgb((lightness, a, b): (f32, f32, f32)) -> LinearRgb {
+    let fy = (lightness + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let x = WHITE_X * lab_f_inv(fx);
+    let y = WHITE_Y * lab_f_inv(fy);
+    let z = WHITE_Z * lab_f_inv(fz);
+    let red = 3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z;
+    let green = -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z;
+    let blue = 0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z;
+    LinearRgb([red, green, blue].map(|val| val.clamp(0.0, 1.0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lab_to_linear_rgb, linear_rgb_to_lab, LinearRgb, SrgbRgb};
+
+    #[test]
+    fn lab_round_trip() {
+        let steps = [0.0, 0.1, 0.25, 0.5, 0.75, 1.0];
+        for red in steps {
+            for green in steps {
+                for blue in steps {
+                    let LinearRgb([r, g, b]) =
+                        lab_to_linear_rgb(linear_rgb_to_lab(LinearRgb([red, green, blue])));
+                    assert!((r - red).abs() < 1e-4);
+                    assert!((g - green).abs() < 1e-4);
+                    assert!((b - blue).abs() < 1e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lab_black_and_white() {
+        let (lightness, _, _) = linear_rgb_to_lab(LinearRgb([0.0; 3]));
+        assert!(lightness.abs() < 1e-4);
+        let (lightness, a, b) = linear_rgb_to_lab(LinearRgb([1.0; 3]));
+        assert!((lightness - 100.0).abs() < 1e-2);
+        assert!(a.abs() < 1e-2);
+        assert!(b.abs() < 1e-2);
+    }
+
+    #[test]
+    fn luminance() {
+        assert!(LinearRgb([0.0; 3]).luminance().abs() < 1e-6);
+        assert!((LinearRgb([1.0; 3]).luminance() - 1.0).abs() < 1e-6);
+        assert!(LinearRgb([0.0, 1.0, 0.0]).luminance() > LinearRgb([1.0, 0.0, 0.0]).luminance());
+        assert!(LinearRgb([1.0, 0.0, 0.0]).luminance() > LinearRgb([0.0, 0.0, 1.0]).luminance());
+    }
+
+    #[test]
+    fn srgb_round_trip() {
+        let LinearRgb([red, green, blue]) = SrgbRgb([0.0, 0.5, 1.0]).to_linear();
+        assert!(red.abs() < 1e-6);
+        assert!((green - 0.214).abs() < 1e-3);
+        assert!((blue - 1.0).abs() < 1e-6);
+        let SrgbRgb([red, green, blue]) = LinearRgb([0.0, 0.214_041_14, 1.0]).to_srgb();
+        assert!(red.abs() < 1e-6);
+        assert!((green - 0.5).abs() < 1e-4);
+        assert!((blue - 1.0).abs() < 1e-6);
+    }
+}
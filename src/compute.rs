@@ -0,0 +1,421 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{num::NonZeroUsize, ops::Range};
+
+use crate::{
+    BinSink, FilteredWaveformBin, FilteredWaveformBinF32, WaveformBinF32, WaveformFilter,
+    WaveformFilterConfig,
+};
+
+fn compute_waveform_with_filter(
+    mut filter: WaveformFilter,
+    samples: &[f32],
+) -> Vec<FilteredWaveformBin> {
+    let mut bins = Vec::new();
+    filter.process_into(samples, &mut bins);
+    filter.finish_into(&mut bins);
+    bins
+}
+
+/// Compute all bins of a waveform at once
+///
+/// Returns an empty `Vec` for empty input. Otherwise the trailing,
+/// partially filled bin is included, i.e. a single sample results in
+/// a single bin and no empty bins are produced. All other batch
+/// functions follow the same contract.
+#[must_use]
+pub fn compute_waveform(config: WaveformFilterConfig, samples: &[f32]) -> Vec<FilteredWaveformBin> {
+    compute_waveform_with_filter(WaveformFilter::new(config), samples)
+}
+
+/// Compute all bins of a waveform at once with an upper bound on the number of bins
+///
+/// Protects against unbounded memory consumption for inputs of arbitrary length.
+///
+/// When the cap is hit the bins become coarser, i.e. the number of samples
+/// per bin is increased as needed. The input is never truncated. The actual
+/// number of samples per bin is given by
+/// [`WaveformFilterConfig::bounded_samples_per_bin()`].
+#[must_use]
+pub fn compute_waveform_bounded(
+    config: WaveformFilterConfig,
+    samples: &[f32],
+    max_bins: NonZeroUsize,
+) -> Vec<FilteredWaveformBin> {
+    let samples_per_bin = config.bounded_samples_per_bin(samples.len(), max_bins);
+    compute_waveform_with_filter(
+        WaveformFilter::with_samples_per_bin(config, samples_per_bin),
+        samples,
+    )
+}
+
+/// Compute all bins of a waveform at once and the maximum energy before clamping
+///
+/// A calibration aid, see [`WaveformFilter::max_unclamped_energy()`].
+#[must_use]
+pub fn compute_waveform_with_max_unclamped_energy(
+    config: WaveformFilterConfig,
+    samples: &[f32],
+) -> (Vec<FilteredWaveformBin>, f32) {
+    let mut filter = WaveformFilter::new(config);
+    let mut bins = Vec::new();
+    filter.process_into(samples, &mut bins);
+    let (last_bin, max_unclamped_energy) = filter.finish_with_max_unclamped_energy();
+    bins.extend(last_bin);
+    (bins, max_unclamped_energy)
+}
+
+/// Compute the bins of a sub-range of samples
+///
+/// The filters are settled by processing up to `warmup_samples` samples
+/// preceding the range without emitting any bins. The first bin starts
+/// at `range.start`. This allows to render a zoomed-in region with
+/// accurate bands without processing all samples.
+///
+/// # Panics
+///
+/// Panics if `range` is out of bounds.
+#[must_use]
+pub fn compute_waveform_range(
+    config: WaveformFilterConfig,
+    samples: &[f32],
+    range: Range<usize>,
+    warmup_samples: usize,
+) -> Vec<FilteredWaveformBin> {
+    let warmup_start = range.start.saturating_sub(warmup_samples);
+    let mut filter = WaveformFilter::new(config);
+    filter.warm_up(&samples[warmup_start..range.start]);
+    compute_waveform_with_filter(filter, &samples[range])
+}
+
+fn compute_waveform_unquantized_with_filter(
+    mut filter: WaveformFilter,
+    samples: &[f32],
+) -> Vec<FilteredWaveformBinF32> {
+    let mut bins = samples
+        .iter()
+        .filter_map(|&sample| filter.add_sample_unquantized(sample))
+        .collect::<Vec<_>>();
+    bins.extend(filter.finish_unquantized());
+    bins
+}
+
+fn phase_average(
+    bin: WaveformBinF32,
+    left: WaveformBinF32,
+    right: WaveformBinF32,
+) -> WaveformBinF32 {
+    let average = |center: f32, left: f32, right: f32| 0.5 * center + 0.25 * (left + right);
+    WaveformBinF32 {
+        peak: average(bin.peak, left.peak, right.peak),
+        energy: average(bin.energy, left.energy, right.energy),
+    }
+}
+
+fn filtered_phase_average(
+    bin: &FilteredWaveformBinF32,
+    left: &FilteredWaveformBinF32,
+    right: &FilteredWaveformBinF32,
+) -> FilteredWaveformBinF32 {
+    FilteredWaveformBinF32 {
+        all: phase_average(bin.all, left.all, right.all),
+        low: phase_average(bin.low, left.low, right.low),
+        mid: phase_average(bin.mid, left.mid, right.mid),
+        high: phase_average(bin.high, left.high, right.high),
+    }
+}
+
+/// Compute all bins of a waveform at once with reduced variance
+///
+/// The samples are processed twice, the second time with all bin
+/// boundaries shifted by half a bin. Each bin is averaged with the
+/// two shifted bins that overlap it, weighted by half and a quarter
+/// respectively. This smoothes variations that are caused by the
+/// arbitrary placement of the bin boundaries while preserving both
+/// the number and the positions of the bins.
+///
+/// The values are averaged before quantization.
+#[must_use]
+pub fn compute_waveform_phase_averaged(
+    config: WaveformFilterConfig,
+    samples: &[f32],
+) -> Vec<FilteredWaveformBin> {
+    let samples_per_bin = config.samples_per_bin();
+    let bins =
+        compute_waveform_unquantized_with_filter(WaveformFilter::new(config.clone()), samples);
+    let mut shifted_filter = WaveformFilter::new(config);
+    shifted_filter.shorten_first_bin(samples_per_bin / 2.0);
+    let shifted_bins = compute_waveform_unquantized_with_filter(shifted_filter, samples);
+    bins.iter()
+        .enumerate()
+        .map(|(index, bin)| {
+            // The trailing shifted bins might be missing if the
+            // trailing bin is only partially filled.
+            let left = shifted_bins.get(index).unwrap_or(bin);
+            let right = shifted_bins.get(index + 1).unwrap_or(left);
+            filtered_phase_average(bin, left, right).quantize()
+        })
+        .collect()
+}
+
+/// Full-resolution bins and overview bins that are aggregated online
+#[derive(Debug)]
+struct OverviewSink {
+    full_bins_per_overview_bin: usize,
+    full: Vec<FilteredWaveformBin>,
+    overview: Vec<FilteredWaveformBin>,
+}
+
+impl OverviewSink {
+    fn push_overview_bin(&mut self) {
+        let group_len = self.full.len() - self.overview.len() * self.full_bins_per_overview_bin;
+        debug_assert!(group_len <= self.full_bins_per_overview_bin);
+        if group_len > 0 {
+            let group = &self.full[self.full.len() - group_len..];
+            self.overview.push(FilteredWaveformBin::combine(group));
+        }
+    }
+}
+
+impl BinSink for OverviewSink {
+    fn push_bin(&mut self, _index: usize, bin: &FilteredWaveformBin) {
+        self.full.push(bin.clone());
+        if self.full.len() % self.full_bins_per_overview_bin == 0 {
+            self.push_overview_bin();
+        }
+    }
+}
+
+/// Compute all bins of a waveform together with a coarse overview
+///
+/// Each overview bin aggregates `full_bins_per_overview_bin` consecutive
+/// bins, see [`FilteredWaveformBin::combine()`], i.e. peaks are preserved.
+/// The aggregation is done online in a single pass. The trailing overview
+/// bin might aggregate fewer bins.
+///
+/// Returns the full-resolution bins and the overview bins in this order.
+#[must_use]
+pub fn compute_waveform_with_overview(
+    config: WaveformFilterConfig,
+    samples: &[f32],
+    full_bins_per_overview_bin: NonZeroUsize,
+) -> (Vec<FilteredWaveformBin>, Vec<FilteredWaveformBin>) {
+    let mut filter = WaveformFilter::new(config);
+    let mut sink = OverviewSink {
+        full_bins_per_overview_bin: full_bins_per_overview_bin.get(),
+        full: Vec::new(),
+        overview: Vec::new(),
+    };
+    filter.process_into(samples, &mut sink);
+    filter.finish_into(&mut sink);
+    if sink.full.len() % sink.full_bins_per_overview_bin != 0 {
+        sink.push_overview_bin();
+    }
+    let OverviewSink { full, overview, .. } = sink;
+    (full, overview)
+}
+
+/// Number of samples between checking for cancellation
+const CANCELLATION_CHECK_INTERVAL: usize = 4096;
+
+/// Compute all bins of a waveform at once unless cancelled
+///
+/// The predicate `should_cancel` is checked periodically, i.e. every few
+/// thousand samples. Returns `None` if cancellation has been requested.
+#[must_use]
+pub fn compute_waveform_cancellable(
+    config: WaveformFilterConfig,
+    samples: &[f32],
+    should_cancel: &dyn Fn() -> bool,
+) -> Option<Vec<FilteredWaveformBin>> {
+    let mut filter = WaveformFilter::new(config);
+    let mut bins = Vec::new();
+    for chunk in samples.chunks(CANCELLATION_CHECK_INTERVAL) {
+        if should_cancel() {
+            return None;
+        }
+        filter.process_into(chunk, &mut bins);
+    }
+    bins.extend(filter.finish());
+    Some(bins)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, num::NonZeroUsize};
+
+    use crate::{FilteredWaveformBin, WaveformFilterConfig, WaveformVal};
+
+    use super::{
+        compute_waveform, compute_waveform_bounded, compute_waveform_cancellable,
+        compute_waveform_phase_averaged, compute_waveform_range,
+        compute_waveform_with_max_unclamped_energy, compute_waveform_with_overview,
+    };
+
+    type BatchFn<'a> = &'a dyn Fn(&[f32]) -> Vec<FilteredWaveformBin>;
+
+    #[test]
+    fn empty_and_short_inputs() {
+        let config = WaveformFilterConfig::DEFAULT;
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        let samples_per_bin = config.samples_per_bin() as usize;
+        let batch_fns: [BatchFn<'_>; 6] = [
+            &|samples| compute_waveform(config.clone(), samples),
+            &|samples| compute_waveform_phase_averaged(config.clone(), samples),
+            &|samples| compute_waveform_bounded(config.clone(), samples, NonZeroUsize::MIN),
+            &|samples| compute_waveform_with_max_unclamped_energy(config.clone(), samples).0,
+            &|samples| compute_waveform_cancellable(config.clone(), samples, &|| false).unwrap(),
+            &|samples| compute_waveform_with_overview(config.clone(), samples, NonZeroUsize::MIN).1,
+        ];
+        for batch_fn in batch_fns {
+            assert!(batch_fn(&[]).is_empty());
+            assert_eq!(1, batch_fn(&[0.5]).len());
+            assert_eq!(1, batch_fn(&vec![0.5; samples_per_bin]).len());
+        }
+        assert_eq!(
+            2,
+            compute_waveform(config, &vec![0.5; samples_per_bin + 1]).len()
+        );
+    }
+
+    #[test]
+    fn max_unclamped_energy() {
+        let (bins, max_unclamped_energy) =
+            compute_waveform_with_max_unclamped_energy(Default::default(), &[]);
+        assert!(bins.is_empty());
+        assert!(max_unclamped_energy.abs() < f32::EPSILON);
+        // Full-scale square wave
+        let samples = (0..10_000)
+            .map(|i| if i % 2 == 0 { 1.0 } else { -1.0 })
+            .collect::<Vec<_>>();
+        let (bins, max_unclamped_energy) =
+            compute_waveform_with_max_unclamped_energy(Default::default(), &samples);
+        // Clamped
+        assert!(bins
+            .iter()
+            .all(|bin| bin.all.energy == WaveformVal(u8::MAX)));
+        assert!((max_unclamped_energy - std::f32::consts::SQRT_2).abs() < 1e-3);
+    }
+
+    #[test]
+    fn cancellation() {
+        let samples = vec![0.5; 100_000];
+        let expected_len = compute_waveform(Default::default(), &samples).len();
+        let bins = compute_waveform_cancellable(Default::default(), &samples, &|| false).unwrap();
+        assert_eq!(expected_len, bins.len());
+        let check_count = Cell::new(0);
+        let should_cancel = || {
+            check_count.set(check_count.get() + 1);
+            check_count.get() > 3
+        };
+        assert!(
+            compute_waveform_cancellable(Default::default(), &samples, &should_cancel).is_none()
+        );
+        assert_eq!(4, check_count.get());
+    }
+
+    #[test]
+    fn bounded_bins_are_coarser_but_complete() {
+        let config = WaveformFilterConfig::DEFAULT;
+        let samples = vec![0.5; 300_000];
+        let unbounded = compute_waveform(config.clone(), &samples);
+        for max_bins in [1, 2, 3, 100, 999, unbounded.len()] {
+            let bins = compute_waveform_bounded(
+                config.clone(),
+                &samples,
+                NonZeroUsize::new(max_bins).unwrap(),
+            );
+            assert!(bins.len() <= max_bins);
+            assert!(bins.len() >= max_bins.min(unbounded.len()) / 2);
+        }
+        let bins = compute_waveform_bounded(
+            config,
+            &samples,
+            NonZeroUsize::new(unbounded.len() * 2).unwrap(),
+        );
+        assert_eq!(unbounded.len(), bins.len());
+    }
+
+    #[test]
+    fn range_with_warmup() {
+        let config = WaveformFilterConfig::DEFAULT;
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        let samples_per_bin = config.samples_per_bin() as usize;
+        let samples = (0..samples_per_bin * 100)
+            .map(|i| if i % 100 < 50 { 0.5 } else { -0.5 })
+            .collect::<Vec<_>>();
+        let all_bins = compute_waveform(config.clone(), &samples);
+        let range = samples_per_bin * 50..samples_per_bin * 60;
+        let bins = compute_waveform_range(
+            config.clone(),
+            &samples,
+            range.clone(),
+            samples_per_bin * 10,
+        );
+        assert_eq!(10, bins.len());
+        for (expected, actual) in all_bins[50..60].iter().zip(&bins) {
+            assert_eq!(expected.low.peak, actual.low.peak);
+            assert_eq!(expected.low.energy, actual.low.energy);
+        }
+        // Without warmup the first bin is affected by the transient response
+        let bins = compute_waveform_range(config, &samples, range, 0);
+        assert_ne!(all_bins[50].low.peak, bins[0].low.peak);
+    }
+
+    #[test]
+    fn phase_averaged() {
+        let config = WaveformFilterConfig::DEFAULT;
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        let samples_per_bin = config.samples_per_bin() as usize;
+        // Constant input is not affected
+        let samples = vec![0.5; samples_per_bin * 10];
+        let bins = compute_waveform(config.clone(), &samples);
+        let averaged_bins = compute_waveform_phase_averaged(config.clone(), &samples);
+        assert_eq!(bins.len(), averaged_bins.len());
+        for (bin, averaged_bin) in bins.iter().zip(&averaged_bins) {
+            assert_eq!(bin.all.peak, averaged_bin.all.peak);
+            assert_eq!(bin.all.energy, averaged_bin.all.energy);
+        }
+        // Bursts with a period of 1.5 bins
+        let samples = (0..samples_per_bin * 30)
+            .map(|i| {
+                if i % (samples_per_bin * 3 / 2) < samples_per_bin / 2 {
+                    0.5
+                } else {
+                    0.0
+                }
+            })
+            .collect::<Vec<_>>();
+        let energy_spread = |bins: &[FilteredWaveformBin]| {
+            let energies = bins[1..bins.len() - 1].iter().map(|bin| bin.all.energy.0);
+            energies.clone().max().unwrap() - energies.min().unwrap()
+        };
+        let bins = compute_waveform(config.clone(), &samples);
+        let averaged_bins = compute_waveform_phase_averaged(config, &samples);
+        assert_eq!(bins.len(), averaged_bins.len());
+        assert!(energy_spread(&averaged_bins) < energy_spread(&bins));
+    }
+
+    #[test]
+    fn overview() {
+        let config = WaveformFilterConfig::DEFAULT;
+        let samples = (0..100_000)
+            .map(|i| if i % 100 < 50 { 0.5 } else { -0.5 })
+            .collect::<Vec<_>>();
+        let full_bins_per_overview_bin = NonZeroUsize::new(64).unwrap();
+        let (full, overview) =
+            compute_waveform_with_overview(config.clone(), &samples, full_bins_per_overview_bin);
+        let expected_full = compute_waveform(config, &samples);
+        assert_eq!(expected_full.len(), full.len());
+        assert_eq!(full.len().div_ceil(64), overview.len());
+        for (chunk, overview_bin) in full.chunks(64).zip(&overview) {
+            let expected = FilteredWaveformBin::combine(chunk);
+            assert_eq!(expected.all.peak, overview_bin.all.peak);
+            assert_eq!(expected.high.energy, overview_bin.high.energy);
+        }
+    }
+}
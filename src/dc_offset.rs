@@ -0,0 +1,142 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Measurement of the DC offset
+
+use crate::{FilteredWaveformBin, WaveformFilter, WaveformFilterConfig};
+
+/// Bin with the DC offset of its samples
+#[derive(Debug, Clone, Default)]
+pub struct FilteredWaveformBinWithDcOffset {
+    pub bin: FilteredWaveformBin,
+
+    /// Mean of the raw, signed samples
+    ///
+    /// Values far from zero indicate a DC bias of the source.
+    pub dc_offset: f32,
+}
+
+/// Mean of signed samples
+#[derive(Debug, Default)]
+struct DcOffsetAccumulator {
+    sample_count: u32,
+    sample_sum: f64,
+}
+
+impl DcOffsetAccumulator {
+    fn add_sample(&mut self, sample: f32) {
+        self.sample_count += 1;
+        self.sample_sum += f64::from(sample);
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn finish(&mut self) -> f32 {
+        let Self {
+            sample_count,
+            sample_sum,
+        } = std::mem::take(self);
+        if sample_count == 0 {
+            return 0.0;
+        }
+        (sample_sum / f64::from(sample_count)) as f32
+    }
+}
+
+/// Variant of [`WaveformFilter`] that also measures the DC offset of each bin
+///
+/// Bins are identical to those of [`WaveformFilter`].
+#[derive(Debug)]
+pub struct DcOffsetWaveformFilter {
+    filter: WaveformFilter,
+    dc_offset: DcOffsetAccumulator,
+}
+
+impl Default for DcOffsetWaveformFilter {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl DcOffsetWaveformFilter {
+    #[must_use]
+    pub fn new(config: WaveformFilterConfig) -> Self {
+        Self {
+            filter: WaveformFilter::new(config),
+            dc_offset: Default::default(),
+        }
+    }
+
+    pub fn add_sample(&mut self, sample: f32) -> Option<FilteredWaveformBinWithDcOffset> {
+        // The completed bin doesn't include the new sample. The DC offset
+        // must also be reset for discarded warmup bins.
+        let dc_offset = self
+            .filter
+            .is_bin_complete()
+            .then(|| self.dc_offset.finish());
+        let next_bin = self
+            .filter
+            .add_sample(sample)
+            .map(|bin| FilteredWaveformBinWithDcOffset {
+                bin,
+                dc_offset: dc_offset.unwrap_or_default(),
+            });
+        self.dc_offset.add_sample(sample);
+        next_bin
+    }
+
+    #[must_use]
+    pub fn finish(self) -> Option<FilteredWaveformBinWithDcOffset> {
+        let Self {
+            filter,
+            mut dc_offset,
+        } = self;
+        filter.finish().map(|bin| FilteredWaveformBinWithDcOffset {
+            bin,
+            dc_offset: dc_offset.finish(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::WaveformFilterConfig;
+
+    use super::DcOffsetWaveformFilter;
+
+    #[test]
+    fn dc_offset_per_bin() {
+        let config = WaveformFilterConfig::DEFAULT;
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        let samples_per_bin = config.samples_per_bin() as usize;
+        // Square wave without and with DC offset
+        let samples = (0..samples_per_bin * 2)
+            .map(|i| {
+                let offset = if i < samples_per_bin { 0.0 } else { 0.25 };
+                let sample = if i % 2 == 0 { 0.5 } else { -0.5 };
+                offset + sample
+            })
+            .collect::<Vec<_>>();
+        let mut filter = DcOffsetWaveformFilter::new(config.clone());
+        let mut bins = samples
+            .iter()
+            .filter_map(|&sample| filter.add_sample(sample))
+            .collect::<Vec<_>>();
+        bins.extend(filter.finish());
+        assert_eq!(2, bins.len());
+        assert!(bins[0].dc_offset.abs() < 1e-6);
+        assert!((bins[1].dc_offset - 0.25).abs() < 1e-6);
+        // Discarded warmup bins
+        let mut filter = DcOffsetWaveformFilter::new(WaveformFilterConfig {
+            discard_warmup_bins: 1,
+            ..config
+        });
+        let mut bins = samples
+            .into_iter()
+            .filter_map(|sample| filter.add_sample(sample))
+            .collect::<Vec<_>>();
+        bins.extend(filter.finish());
+        assert_eq!(1, bins.len());
+        assert!((bins[0].dc_offset - 0.25).abs() < 1e-6);
+    }
+}
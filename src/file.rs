@@ -0,0 +1,430 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Self-describing file format for storing waveforms
+//!
+//! All numbers are encoded as little-endian. The header consists of
+//!
+//! - the magic bytes `AVZW`
+//! - the format version as `u16`
+//! - the sample rate and the number of bins per second as `f32`
+//! - the crossover frequencies as `f32` in the order of the fields of
+//!   [`ThreeBandFilterFreqConfig`]
+//! - the peak hold decays of the all, low, mid, and high band, each as
+//!   a `u8` tag (`0`: none, `1`: linear, `2`: dB) followed by the decay
+//!   per second as `f32`
+//! - the energy averaging as a `u8` tag (`0`: block, `1`: exponential)
+//!   followed by the time constant in seconds as `f32`
+//! - the energy scales of the all, low, mid, and high band as `f32`
+//! - the energy metric as `u8` (`0`: RMS, `1`: mean square, `2`: mean
+//!   absolute value, `3`: variance)
+//! - the energy reference level as a `u8` tag (`0`: none, `1`: some)
+//!   followed by the level in dBFS as `f32`
+//! - the source of the all band as `u8` (`0`: wideband, `1`: sum of
+//!   bands)
+//! - the number of bins as `u64`
+//!
+//! Unused `f32` values that follow a tag are stored as `0.0`. Version 1
+//! of the format did not contain the parameters between the crossover
+//! frequencies and the number of bins.
+//!
+//! and is followed by the bins with 8 bytes per bin, i.e. peak and
+//! energy of the all, low, mid, and high band in this order.
+
+use std::io::{self, Read, Write};
+
+use crate::{
+    AllBandSource, EnergyAveraging, EnergyMetric, FilteredEnergyScale, FilteredPeakHoldConfig,
+    FilteredWaveformBin, PeakHoldDecay, ThreeBandFilterFreqConfig, WaveformBin,
+    WaveformFilterConfig, WaveformVal,
+};
+
+const MAGIC: [u8; 4] = *b"AVZW";
+
+const VERSION: u16 = 2;
+
+/// Without the parameters of the peak and energy calculation
+const VERSION_1: u16 = 1;
+
+/// Upper bound for preallocating bins while reading
+const MAX_INITIAL_CAPACITY: usize = 1 << 16;
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_f32(reader: &mut impl Read) -> io::Result<f32> {
+    read_array(reader).map(f32::from_le_bytes)
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    read_array(reader).map(|[val]| val)
+}
+
+fn read_tagged_f32(reader: &mut impl Read) -> io::Result<(u8, f32)> {
+    Ok((read_u8(reader)?, read_f32(reader)?))
+}
+
+fn write_tagged_f32(writer: &mut impl Write, tag: u8, val: f32) -> io::Result<()> {
+    writer.write_all(&[tag])?;
+    writer.write_all(&val.to_le_bytes())
+}
+
+const fn peak_hold_decay_to_tagged(decay: PeakHoldDecay) -> (u8, f32) {
+    match decay {
+        PeakHoldDecay::None => (0, 0.0),
+        PeakHoldDecay::Linear { per_second } => (1, per_second),
+        PeakHoldDecay::Db { per_second } => (2, per_second),
+    }
+}
+
+fn peak_hold_decay_from_tagged((tag, per_second): (u8, f32)) -> io::Result<PeakHoldDecay> {
+    match tag {
+        0 => Ok(PeakHoldDecay::None),
+        1 => Ok(PeakHoldDecay::Linear { per_second }),
+        2 => Ok(PeakHoldDecay::Db { per_second }),
+        _ => Err(invalid_data("invalid peak hold decay")),
+    }
+}
+
+const fn energy_metric_to_u8(energy_metric: EnergyMetric) -> u8 {
+    match energy_metric {
+        EnergyMetric::Rms => 0,
+        EnergyMetric::MeanSquare => 1,
+        EnergyMetric::MeanAbs => 2,
+        EnergyMetric::Variance => 3,
+    }
+}
+
+fn energy_metric_from_u8(val: u8) -> io::Result<EnergyMetric> {
+    match val {
+        0 => Ok(EnergyMetric::Rms),
+        1 => Ok(EnergyMetric::MeanSquare),
+        2 => Ok(EnergyMetric::MeanAbs),
+        3 => Ok(EnergyMetric::Variance),
+        _ => Err(invalid_data("invalid energy metric")),
+    }
+}
+
+fn write_parameters(writer: &mut impl Write, config: &WaveformFilterConfig) -> io::Result<()> {
+    let WaveformFilterConfig {
+        sample_rate_hz: _,
+        bins_per_sec: _,
+        filter_freqs: _,
+        peak_hold,
+        energy_averaging,
+        energy_scale,
+        energy_metric,
+        energy_reference_db,
+        all_source,
+        discard_warmup_bins: _,
+    } = config;
+    let FilteredPeakHoldConfig {
+        all,
+        low,
+        mid,
+        high,
+    } = *peak_hold;
+    for decay in [all, low, mid, high] {
+        let (tag, per_second) = peak_hold_decay_to_tagged(decay);
+        write_tagged_f32(writer, tag, per_second)?;
+    }
+    let (tag, time_constant_secs) = match *energy_averaging {
+        EnergyAveraging::Block => (0, 0.0),
+        EnergyAveraging::Exponential { time_constant_secs } => (1, time_constant_secs),
+    };
+    write_tagged_f32(writer, tag, time_constant_secs)?;
+    let FilteredEnergyScale {
+        all,
+        low,
+        mid,
+        high,
+    } = *energy_scale;
+    for val in [all, low, mid, high] {
+        writer.write_all(&val.to_le_bytes())?;
+    }
+    writer.write_all(&[energy_metric_to_u8(*energy_metric)])?;
+    let (tag, energy_reference_db) = match *energy_reference_db {
+        None => (0, 0.0),
+        Some(energy_reference_db) => (1, energy_reference_db),
+    };
+    write_tagged_f32(writer, tag, energy_reference_db)?;
+    let all_source = match all_source {
+        AllBandSource::Wideband => 0,
+        AllBandSource::SumOfBands => 1,
+    };
+    writer.write_all(&[all_source])
+}
+
+fn read_parameters(
+    reader: &mut impl Read,
+    config: WaveformFilterConfig,
+) -> io::Result<WaveformFilterConfig> {
+    let peak_hold = FilteredPeakHoldConfig {
+        all: peak_hold_decay_from_tagged(read_tagged_f32(reader)?)?,
+        low: peak_hold_decay_from_tagged(read_tagged_f32(reader)?)?,
+        mid: peak_hold_decay_from_tagged(read_tagged_f32(reader)?)?,
+        high: peak_hold_decay_from_tagged(read_tagged_f32(reader)?)?,
+    };
+    let energy_averaging = match read_tagged_f32(reader)? {
+        (0, _) => EnergyAveraging::Block,
+        (1, time_constant_secs) => EnergyAveraging::Exponential { time_constant_secs },
+        _ => return Err(invalid_data("invalid energy averaging")),
+    };
+    let energy_scale = FilteredEnergyScale {
+        all: read_f32(reader)?,
+        low: read_f32(reader)?,
+        mid: read_f32(reader)?,
+        high: read_f32(reader)?,
+    };
+    let energy_metric = energy_metric_from_u8(read_u8(reader)?)?;
+    let energy_reference_db = match read_tagged_f32(reader)? {
+        (0, _) => None,
+        (1, energy_reference_db) => Some(energy_reference_db),
+        _ => return Err(invalid_data("invalid energy reference level")),
+    };
+    let all_source = match read_u8(reader)? {
+        0 => AllBandSource::Wideband,
+        1 => AllBandSource::SumOfBands,
+        _ => return Err(invalid_data("invalid source of the all band")),
+    };
+    Ok(WaveformFilterConfig {
+        peak_hold,
+        energy_averaging,
+        energy_scale,
+        energy_metric,
+        energy_reference_db,
+        all_source,
+        ..config
+    })
+}
+
+const fn bin_to_bytes(bin: &FilteredWaveformBin) -> [u8; 8] {
+    let FilteredWaveformBin {
+        all,
+        low,
+        mid,
+        high,
+    } = bin;
+    [
+        all.peak.0,
+        all.energy.0,
+        low.peak.0,
+        low.energy.0,
+        mid.peak.0,
+        mid.energy.0,
+        high.peak.0,
+        high.energy.0,
+    ]
+}
+
+const fn band_from_bytes(peak: u8, energy: u8) -> WaveformBin {
+    WaveformBin {
+        peak: WaveformVal(peak),
+        energy: WaveformVal(energy),
+    }
+}
+
+const fn bin_from_bytes(bytes: [u8; 8]) -> FilteredWaveformBin {
+    let [all_peak, all_energy, low_peak, low_energy, mid_peak, mid_energy, high_peak, high_energy] =
+        bytes;
+    FilteredWaveformBin {
+        all: band_from_bytes(all_peak, all_energy),
+        low: band_from_bytes(low_peak, low_energy),
+        mid: band_from_bytes(mid_peak, mid_energy),
+        high: band_from_bytes(high_peak, high_energy),
+    }
+}
+
+/// Write a waveform file
+///
+/// Stores all parameters of `config` that affect the values of the bins,
+/// i.e. all except [`WaveformFilterConfig::discard_warmup_bins`].
+///
+/// # Errors
+///
+/// Returns any error of `writer`.
+pub fn write_waveform_file<W: Write>(
+    mut writer: W,
+    config: &WaveformFilterConfig,
+    bins: &[FilteredWaveformBin],
+) -> io::Result<()> {
+    let ThreeBandFilterFreqConfig {
+        low_lp_hz,
+        low_hp_hz,
+        high_lp_hz,
+        high_hp_hz,
+    } = config.filter_freqs;
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    for val in [
+        config.sample_rate_hz,
+        config.bins_per_sec,
+        low_lp_hz,
+        low_hp_hz,
+        high_lp_hz,
+        high_hp_hz,
+    ] {
+        writer.write_all(&val.to_le_bytes())?;
+    }
+    write_parameters(&mut writer, config)?;
+    writer.write_all(&(bins.len() as u64).to_le_bytes())?;
+    for bin in bins {
+        writer.write_all(&bin_to_bytes(bin))?;
+    }
+    writer.flush()
+}
+
+/// Read a waveform file that has been written by [`write_waveform_file()`]
+///
+/// All parameters of the returned config that are not stored in the
+/// file have their default values, i.e. all parameters of the peak and
+/// energy calculation for files of version 1.
+///
+/// # Errors
+///
+/// Returns an error of kind [`io::ErrorKind::InvalidData`] if the
+/// magic bytes do not match, if the version is not supported, or if
+/// the stored config is invalid, see [`WaveformFilterConfig::validate()`].
+/// Any other error of `reader` is passed through, e.g. an error of kind
+/// [`io::ErrorKind::UnexpectedEof`] for truncated files.
+pub fn read_waveform_file<R: Read>(
+    mut reader: R,
+) -> io::Result<(WaveformFilterConfig, Vec<FilteredWaveformBin>)> {
+    if read_array(&mut reader)? != MAGIC {
+        return Err(invalid_data("not a waveform file"));
+    }
+    let version = u16::from_le_bytes(read_array(&mut reader)?);
+    if version != VERSION && version != VERSION_1 {
+        return Err(invalid_data("unsupported version"));
+    }
+    let sample_rate_hz = read_f32(&mut reader)?;
+    let bins_per_sec = read_f32(&mut reader)?;
+    let filter_freqs = ThreeBandFilterFreqConfig {
+        low_lp_hz: read_f32(&mut reader)?,
+        low_hp_hz: read_f32(&mut reader)?,
+        high_lp_hz: read_f32(&mut reader)?,
+        high_hp_hz: read_f32(&mut reader)?,
+    };
+    let mut config = WaveformFilterConfig {
+        sample_rate_hz,
+        bins_per_sec,
+        filter_freqs,
+        ..Default::default()
+    };
+    if version != VERSION_1 {
+        config = read_parameters(&mut reader, config)?;
+    }
+    config
+        .validate()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let bin_count = usize::try_from(u64::from_le_bytes(read_array(&mut reader)?))
+        .map_err(|_| invalid_data("too many bins"))?;
+    let mut bins = Vec::with_capacity(bin_count.min(MAX_INITIAL_CAPACITY));
+    for _ in 0..bin_count {
+        bins.push(bin_from_bytes(read_array(&mut reader)?));
+    }
+    Ok((config, bins))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{
+        compute_waveform, AllBandSource, EnergyAveraging, EnergyMetric, PeakHoldDecay,
+        WaveformFilterConfig,
+    };
+
+    use super::{read_waveform_file, write_waveform_file};
+
+    #[test]
+    fn round_trip() {
+        let mut config = WaveformFilterConfig::DEFAULT;
+        config.sample_rate_hz = 48_000.0;
+        config.bins_per_sec = 100.0;
+        let samples = (0..10_000)
+            .map(|i| if i % 20 < 10 { 0.5 } else { -0.5 })
+            .collect::<Vec<_>>();
+        let bins = compute_waveform(config.clone(), &samples);
+        let mut file = Vec::new();
+        write_waveform_file(&mut file, &config, &bins).unwrap();
+        assert_eq!(86 + 8 * bins.len(), file.len());
+        let (read_config, read_bins) = read_waveform_file(file.as_slice()).unwrap();
+        assert_eq!(config, read_config);
+        assert_eq!(bins.len(), read_bins.len());
+        for (bin, read_bin) in bins.iter().zip(&read_bins) {
+            assert_eq!(bin.all.peak, read_bin.all.peak);
+            assert_eq!(bin.low.energy, read_bin.low.energy);
+            assert_eq!(bin.high.peak, read_bin.high.peak);
+        }
+        // Truncated
+        let err = read_waveform_file(&file[..file.len() - 1]).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+        // Unsupported version
+        file[4] = 3;
+        let err = read_waveform_file(file.as_slice()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        // Not a waveform file
+        let err = read_waveform_file(&b"RIFF\x01\x00"[..]).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn round_trip_parameters() {
+        let mut config = WaveformFilterConfig::DEFAULT;
+        config.peak_hold.low = PeakHoldDecay::Db { per_second: 20.0 };
+        config.peak_hold.high = PeakHoldDecay::Linear { per_second: 0.5 };
+        config.energy_averaging = EnergyAveraging::Exponential {
+            time_constant_secs: 0.1,
+        };
+        config.energy_scale.mid = 2.0;
+        config.energy_metric = EnergyMetric::MeanSquare;
+        config.energy_reference_db = Some(-18.0);
+        config.all_source = AllBandSource::SumOfBands;
+        let mut file = Vec::new();
+        write_waveform_file(&mut file, &config, &[]).unwrap();
+        let (read_config, read_bins) = read_waveform_file(file.as_slice()).unwrap();
+        assert_eq!(config, read_config);
+        assert!(read_bins.is_empty());
+        // Version 1 without these parameters
+        let mut file_v1 = file[..30].to_vec();
+        file_v1[4] = 1;
+        file_v1.extend_from_slice(&file[78..]);
+        let (read_config, _) = read_waveform_file(file_v1.as_slice()).unwrap();
+        assert_eq!(WaveformFilterConfig::DEFAULT, read_config);
+    }
+
+    #[test]
+    fn corrupted_header() {
+        let mut file = Vec::new();
+        write_waveform_file(&mut file, &WaveformFilterConfig::DEFAULT, &[]).unwrap();
+        let corrupted = |offset: usize, bytes: &[u8]| {
+            let mut file = file.clone();
+            file[offset..offset + bytes.len()].copy_from_slice(bytes);
+            read_waveform_file(file.as_slice()).unwrap_err().kind()
+        };
+        // Sample rate
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            corrupted(6, &f32::NAN.to_le_bytes())
+        );
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            corrupted(6, &0f32.to_le_bytes())
+        );
+        // Unordered crossovers
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            corrupted(14, &5000f32.to_le_bytes())
+        );
+        // Invalid tag of the energy metric
+        assert_eq!(io::ErrorKind::InvalidData, corrupted(71, &[4]));
+    }
+}
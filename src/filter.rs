@@ -1,9 +1,11 @@
 // SPDX-FileCopyrightText: The audio-viz authors
 // SPDX-License-Identifier: MPL-2.0
 
-use biquad::{Biquad as _, Coefficients, DirectForm2Transposed, Hertz, Q_BUTTERWORTH_F32};
+use biquad::{Biquad as _, Coefficients, DirectForm2Transposed, Q_BUTTERWORTH_F32};
 
-use super::{FilteredWaveformBin, WaveformBin, WaveformVal};
+use std::{num::NonZeroUsize, sync::Arc};
+
+use super::{FilteredWaveformBin, FilteredWaveformBinF32, WaveformBin, WaveformBinF32};
 
 // Only needed for default initialization.
 const DEFAULT_SAMPLE_RATE_HZ: f32 = 44_100.0;
@@ -36,7 +38,13 @@ const DEFAULT_HIGH_LP_FILTER_HZ: f32 = 1600.0;
 /// Crossover mid/high (high pass)
 const DEFAULT_HIGH_HP_FILTER_HZ: f32 = 1200.0;
 
+/// Crossover frequencies of the bands
+///
+/// Outside of this crate the config is created from [`Self::DEFAULT`]
+/// or one of the presets and then customized by the `with_*()` builder
+/// methods, see [`WaveformFilterConfig`].
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub struct ThreeBandFilterFreqConfig {
     pub low_lp_hz: f32,
     pub low_hp_hz: f32,
@@ -48,12 +56,91 @@ impl ThreeBandFilterFreqConfig {
     pub const MIN_FREQ_HZ: f32 = 20.0;
     pub const MAX_FREQ_HZ: f32 = 20_000.0;
 
+    /// Minimum ratio between the upper and the lower edge of the mid band
+    ///
+    /// The mid band must span at least half an octave, i.e. `high_lp_hz`
+    /// must be at least `SQRT_2` times `low_hp_hz`. Otherwise the mid
+    /// band becomes numerically unstable and its energy vanishes.
+    pub const MIN_MID_BAND_RATIO: f32 = std::f32::consts::SQRT_2;
+
     pub const DEFAULT: Self = Self {
         low_lp_hz: DEFAULT_LOW_LP_FILTER_HZ,
         low_hp_hz: DEFAULT_LOW_HP_FILTER_HZ,
         high_lp_hz: DEFAULT_HIGH_LP_FILTER_HZ,
         high_hp_hz: DEFAULT_HIGH_HP_FILTER_HZ,
     };
+
+    /// Replace [`Self::low_lp_hz`]
+    #[must_use]
+    pub const fn with_low_lp_hz(self, low_lp_hz: f32) -> Self {
+        Self { low_lp_hz, ..self }
+    }
+
+    /// Replace [`Self::low_hp_hz`]
+    #[must_use]
+    pub const fn with_low_hp_hz(self, low_hp_hz: f32) -> Self {
+        Self { low_hp_hz, ..self }
+    }
+
+    /// Replace [`Self::high_lp_hz`]
+    #[must_use]
+    pub const fn with_high_lp_hz(self, high_lp_hz: f32) -> Self {
+        Self { high_lp_hz, ..self }
+    }
+
+    /// Replace [`Self::high_hp_hz`]
+    #[must_use]
+    pub const fn with_high_hp_hz(self, high_hp_hz: f32) -> Self {
+        Self { high_hp_hz, ..self }
+    }
+
+    /// Bands similar to [Rekordbox](https://rekordbox.com)
+    ///
+    /// Crossovers at ~200/2000 Hz:
+    ///
+    /// - `low_lp_hz`: 200 Hz
+    /// - `low_hp_hz`: 160 Hz
+    /// - `high_lp_hz`: 2000 Hz
+    /// - `high_hp_hz`: 1500 Hz
+    ///
+    /// The mid band overlaps with the low and high bands by the same
+    /// ratios as in [`Self::DEFAULT`].
+    #[must_use]
+    pub const fn rekordbox() -> Self {
+        Self {
+            low_lp_hz: 200.0,
+            low_hp_hz: 160.0,
+            high_lp_hz: 2000.0,
+            high_hp_hz: 1500.0,
+        }
+    }
+
+    /// Bands similar to [Superpowered](https://docs.superpowered.com/reference/latest/analyzer)
+    ///
+    /// Crossovers at 200/1600 Hz, identical to [`Self::DEFAULT`]:
+    ///
+    /// - `low_lp_hz`: 200 Hz
+    /// - `low_hp_hz`: 160 Hz
+    /// - `high_lp_hz`: 1600 Hz
+    /// - `high_hp_hz`: 1200 Hz
+    #[must_use]
+    pub const fn superpowered() -> Self {
+        Self::DEFAULT
+    }
+
+    /// Check if the config equals [`Self::DEFAULT`]
+    ///
+    /// Frequencies are compared exactly, i.e. a config that has been
+    /// edited and then manually restored to approximately the default
+    /// values might not be considered as default.
+    #[must_use]
+    pub fn is_default(&self) -> bool {
+        *self == Self::DEFAULT
+    }
+
+    pub fn reset_to_default(&mut self) {
+        *self = Self::DEFAULT;
+    }
 }
 
 impl Default for ThreeBandFilterFreqConfig {
@@ -62,70 +149,161 @@ impl Default for ThreeBandFilterFreqConfig {
     }
 }
 
-// 3-band crossover using 4th-order Linkwitz-Riley (LR4) LP/HP filters (2 cascaded 2nd-order Butterworth)
-// and two 2nd-order Butterworth LP/HP filters for the mid band.
-#[derive(Debug)]
-struct ThreeBandFilterBank {
-    low_lp: [DirectForm2Transposed<f32>; 2],
-    mid_bp: [DirectForm2Transposed<f32>; 2],
-    high_hp: [DirectForm2Transposed<f32>; 2],
+#[derive(Debug, Clone, Copy)]
+enum ButterworthType {
+    LowPass,
+    HighPass,
 }
 
-impl ThreeBandFilterBank {
-    #[allow(clippy::needless_pass_by_value)]
-    fn new(fs: Hertz<f32>, config: ThreeBandFilterFreqConfig) -> Self {
+/// Coefficients of a 2nd-order Butterworth LP/HP filter
+///
+/// Identical to [`Coefficients::from_params()`] with [`Q_BUTTERWORTH_F32`],
+/// but without checking the parameters.
+fn butterworth_coefficients(
+    filter_type: ButterworthType,
+    sample_rate_hz: f32,
+    cutoff_hz: f32,
+) -> Coefficients<f32> {
+    let omega = 2.0 * std::f32::consts::PI * cutoff_hz / sample_rate_hz;
+    let omega_s = omega.sin();
+    let omega_c = omega.cos();
+    let alpha = omega_s / (2.0 * Q_BUTTERWORTH_F32);
+    let (b0, b1, b2) = match filter_type {
+        ButterworthType::LowPass => ((1.0 - omega_c) * 0.5, 1.0 - omega_c, (1.0 - omega_c) * 0.5),
+        ButterworthType::HighPass => (
+            (1.0 + omega_c) * 0.5,
+            -(1.0 + omega_c),
+            (1.0 + omega_c) * 0.5,
+        ),
+    };
+    let a0 = 1.0 + alpha;
+    let a1 = -2.0 * omega_c;
+    let a2 = 1.0 - alpha;
+    Coefficients {
+        a1: a1 / a0,
+        a2: a2 / a0,
+        b0: b0 / a0,
+        b1: b1 / a0,
+        b2: b2 / a0,
+    }
+}
+
+/// Precomputed coefficients of the crossover filters
+///
+/// Immutable and independent of any filter state. Could be calculated
+/// once and then shared between many filters with the same sample rate
+/// and crossover frequencies, see [`WaveformFilter::with_coefficients()`].
+#[derive(Debug, Clone)]
+pub struct ThreeBandFilterCoefficients {
+    sample_rate_hz: f32,
+    filter_freqs: ThreeBandFilterFreqConfig,
+    low_lp: Coefficients<f32>,
+    low_hp: Coefficients<f32>,
+    high_lp: Coefficients<f32>,
+    high_hp: Coefficients<f32>,
+}
+
+impl ThreeBandFilterCoefficients {
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn new(sample_rate_hz: f32, filter_freqs: ThreeBandFilterFreqConfig) -> Self {
+        assert_valid_filter_params(sample_rate_hz, &filter_freqs);
+        Self::new_unchecked(sample_rate_hz, filter_freqs)
+    }
+
+    /// Calculate the coefficients without checking the parameters
+    ///
+    /// The sample rate must be positive and all frequencies must be
+    /// positive and not exceed the Nyquist frequency.
+    pub(crate) fn new_unchecked(
+        sample_rate_hz: f32,
+        filter_freqs: ThreeBandFilterFreqConfig,
+    ) -> Self {
         let ThreeBandFilterFreqConfig {
             low_lp_hz,
             low_hp_hz,
             high_lp_hz,
             high_hp_hz,
-        } = config;
+        } = filter_freqs;
         debug_assert!(low_hp_hz >= ThreeBandFilterFreqConfig::MIN_FREQ_HZ);
         debug_assert!(low_hp_hz <= low_lp_hz); // Overlapping mids with lows
         debug_assert!(low_lp_hz < high_hp_hz); // Non-empty mids
         debug_assert!(high_hp_hz <= high_lp_hz); // Overlapping mids with highs
         debug_assert!(high_lp_hz <= ThreeBandFilterFreqConfig::MAX_FREQ_HZ);
-        let low_lp_f0 = Hertz::<f32>::from_hz(low_lp_hz).expect("valid frequency");
-        let low_lp = DirectForm2Transposed::<f32>::new(
-            Coefficients::<f32>::from_params(
-                biquad::Type::LowPass,
-                fs,
-                low_lp_f0,
-                Q_BUTTERWORTH_F32,
-            )
-            .expect("valid params"),
-        );
-        let low_hp_f0 = Hertz::<f32>::from_hz(low_hp_hz).expect("valid frequency");
-        let low_hp = DirectForm2Transposed::<f32>::new(
-            Coefficients::<f32>::from_params(
-                biquad::Type::HighPass,
-                fs,
-                low_hp_f0,
-                Q_BUTTERWORTH_F32,
-            )
-            .expect("valid params"),
-        );
-        let high_lp_f0 = Hertz::<f32>::from_hz(high_lp_hz).expect("valid frequency");
-        let high_lp = DirectForm2Transposed::<f32>::new(
-            Coefficients::<f32>::from_params(
-                biquad::Type::LowPass,
-                fs,
-                high_lp_f0,
-                Q_BUTTERWORTH_F32,
-            )
-            .expect("valid params"),
-        );
-        let high_hp_f0 = Hertz::<f32>::from_hz(high_hp_hz).expect("valid frequency");
-        let high_hp = DirectForm2Transposed::<f32>::new(
-            Coefficients::<f32>::from_params(
-                biquad::Type::HighPass,
-                fs,
-                high_hp_f0,
-                Q_BUTTERWORTH_F32,
-            )
-            .expect("valid params"),
-        );
         Self {
+            sample_rate_hz,
+            filter_freqs,
+            low_lp: butterworth_coefficients(ButterworthType::LowPass, sample_rate_hz, low_lp_hz),
+            low_hp: butterworth_coefficients(ButterworthType::HighPass, sample_rate_hz, low_hp_hz),
+            high_lp: butterworth_coefficients(ButterworthType::LowPass, sample_rate_hz, high_lp_hz),
+            high_hp: butterworth_coefficients(
+                ButterworthType::HighPass,
+                sample_rate_hz,
+                high_hp_hz,
+            ),
+        }
+    }
+
+    #[must_use]
+    pub const fn sample_rate_hz(&self) -> f32 {
+        self.sample_rate_hz
+    }
+
+    #[must_use]
+    pub const fn filter_freqs(&self) -> &ThreeBandFilterFreqConfig {
+        &self.filter_freqs
+    }
+
+    const fn to_arrays(&self) -> [[f32; 5]; 6] {
+        const fn to_array(coefficients: Coefficients<f32>) -> [f32; 5] {
+            let Coefficients { a1, a2, b0, b1, b2 } = coefficients;
+            [a1, a2, b0, b1, b2]
+        }
+        let Self {
+            sample_rate_hz: _,
+            filter_freqs: _,
+            low_lp,
+            low_hp,
+            high_lp,
+            high_hp,
+        } = *self;
+        [
+            to_array(low_lp),
+            to_array(low_lp),
+            to_array(low_hp),
+            to_array(high_lp),
+            to_array(high_hp),
+            to_array(high_hp),
+        ]
+    }
+}
+
+// 3-band crossover using 4th-order Linkwitz-Riley (LR4) LP/HP filters (2 cascaded 2nd-order Butterworth)
+// and two 2nd-order Butterworth LP/HP filters for the mid band.
+#[derive(Debug)]
+pub(crate) struct ThreeBandFilterBank {
+    coefficients: Arc<ThreeBandFilterCoefficients>,
+    low_lp: [DirectForm2Transposed<f32>; 2],
+    mid_bp: [DirectForm2Transposed<f32>; 2],
+    high_hp: [DirectForm2Transposed<f32>; 2],
+}
+
+impl ThreeBandFilterBank {
+    pub(crate) fn new(coefficients: Arc<ThreeBandFilterCoefficients>) -> Self {
+        let ThreeBandFilterCoefficients {
+            sample_rate_hz: _,
+            filter_freqs: _,
+            low_lp,
+            low_hp,
+            high_lp,
+            high_hp,
+        } = *coefficients;
+        let low_lp = DirectForm2Transposed::<f32>::new(low_lp);
+        let low_hp = DirectForm2Transposed::<f32>::new(low_hp);
+        let high_lp = DirectForm2Transposed::<f32>::new(high_lp);
+        let high_hp = DirectForm2Transposed::<f32>::new(high_hp);
+        Self {
+            coefficients,
             low_lp: [low_lp, low_lp],
             mid_bp: [low_hp, high_lp],
             high_hp: [high_hp, high_hp],
@@ -133,28 +311,26 @@ impl ThreeBandFilterBank {
     }
 
     #[allow(clippy::unused_self)] // TODO
+    #[inline]
     fn shape_input_signal(&mut self, sample: f32) -> f32 {
         // TODO: Apply filtering to shape the input signal according to the
         // ISO 226:2003 equal-loudness-level contour at 40 phons (A-weighting).
         sample
     }
 
-    fn run(&mut self, sample: f32) -> FilteredSample {
+    #[inline]
+    pub(crate) fn run(&mut self, sample: f32) -> FilteredSample {
         let all = self.shape_input_signal(sample);
         let Self {
-            low_lp,
-            mid_bp,
-            high_hp,
+            coefficients: _,
+            low_lp: [low_lp_0, low_lp_1],
+            mid_bp: [mid_bp_0, mid_bp_1],
+            high_hp: [high_hp_0, high_hp_1],
         } = self;
-        let low = low_lp
-            .iter_mut()
-            .fold(all, |sample, filter| filter.run(sample));
-        let mid = mid_bp
-            .iter_mut()
-            .fold(all, |sample, filter| filter.run(sample));
-        let high = high_hp
-            .iter_mut()
-            .fold(all, |sample, filter| filter.run(sample));
+        // Explicitly unrolled instead of folding the filter cascades.
+        let low = low_lp_1.run(low_lp_0.run(all));
+        let mid = mid_bp_1.run(mid_bp_0.run(all));
+        let high = high_hp_1.run(high_hp_0.run(all));
         FilteredSample {
             all,
             low,
@@ -164,83 +340,362 @@ impl ThreeBandFilterBank {
     }
 }
 
+/// Compensated (Kahan) summation
+///
+/// Reduces the accumulated rounding error when summing up
+/// many small values, e.g. for very long bins.
+#[derive(Debug, Default, Clone, Copy)]
+struct CompensatedSum {
+    sum: f64,
+    compensation: f64,
+}
+
+impl CompensatedSum {
+    #[inline]
+    fn add(&mut self, value: f64) {
+        let Self { sum, compensation } = *self;
+        let value = value - compensation;
+        let next_sum = sum + value;
+        self.compensation = (next_sum - sum) - value;
+        self.sum = next_sum;
+    }
+
+    const fn sum(self) -> f64 {
+        self.sum
+    }
+}
+
+/// Accumulates the peak and energy of a single band
+///
+/// The building block for custom binning schemes that require control
+/// over the bin boundaries, e.g. for non-uniform bins.
 #[derive(Debug, Default)]
-struct WaveformBinAccumulator {
+pub struct WaveformBinAccumulator {
+    sample_count: u32,
     peak: f32,
-    rms_sum: f64,
+    rms_sum: CompensatedSum,
+    abs_sum: f64,
+    sum: f64,
 }
 
-#[derive(Debug)]
-struct FilteredSample {
-    all: f32,
-    low: f32,
-    mid: f32,
-    high: f32,
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FilteredSample {
+    pub(crate) all: f32,
+    pub(crate) low: f32,
+    pub(crate) mid: f32,
+    pub(crate) high: f32,
+}
+
+/// Default scaling factor of the RMS
+///
+/// For a sinusoidal signal, the peak value equals `SQRT_2` times the RMS.
+/// This is a good enough approximation of our expected input signal and
+/// we scale the RMS accordingly.
+const DEFAULT_ENERGY_SCALE: f32 = std::f32::consts::SQRT_2;
+
+/// Scaled RMS at [`WaveformFilterConfig::energy_reference_db`]
+///
+/// The resulting energy depends on the [`EnergyMetric`].
+const REFERENCE_ENERGY: f32 = 0.5;
+
+/// Factor of the energy scale for [`WaveformFilterConfig::energy_reference_db`]
+///
+/// Infinite for extremely low reference levels.
+fn energy_reference_factor(energy_reference_db: f32) -> f32 {
+    let reference_rms = 10.0f32.powf(energy_reference_db / 20.0);
+    REFERENCE_ENERGY / (reference_rms * DEFAULT_ENERGY_SCALE)
+}
+
+/// Scaling factor of the RMS for each band
+///
+/// Noise-like high frequencies and tonal low frequencies have different
+/// ratios between peak and RMS. Scaling the energy of each band independently
+/// allows to balance the bands for display.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilteredEnergyScale {
+    pub all: f32,
+    pub low: f32,
+    pub mid: f32,
+    pub high: f32,
+}
+
+impl FilteredEnergyScale {
+    pub const DEFAULT: Self = Self {
+        all: DEFAULT_ENERGY_SCALE,
+        low: DEFAULT_ENERGY_SCALE,
+        mid: DEFAULT_ENERGY_SCALE,
+        high: DEFAULT_ENERGY_SCALE,
+    };
+}
+
+impl Default for FilteredEnergyScale {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Signal of the `all` band
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllBandSource {
+    /// The unfiltered, wideband input signal
+    ///
+    /// Independent of the crossover frequencies, i.e. the `all` energy
+    /// usually differs from the combined energies of the bands.
+    Wideband,
+
+    /// The sum of the filtered low, mid, and high signals
+    ///
+    /// Ensures that the `all` band is consistent with the other bands,
+    /// e.g. for stacked visualizations. The bands overlap, i.e. the sum
+    /// slightly emphasizes the frequencies around the crossovers.
+    SumOfBands,
+}
+
+impl AllBandSource {
+    pub const DEFAULT: Self = Self::Wideband;
+
+    pub(crate) fn apply(self, filtered_sample: FilteredSample) -> FilteredSample {
+        match self {
+            Self::Wideband => filtered_sample,
+            Self::SumOfBands => {
+                let FilteredSample {
+                    all: _,
+                    low,
+                    mid,
+                    high,
+                } = filtered_sample;
+                FilteredSample {
+                    all: low + mid + high,
+                    low,
+                    mid,
+                    high,
+                }
+            }
+        }
+    }
+}
+
+impl Default for AllBandSource {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Quantity that is reported as the energy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnergyMetric {
+    /// Root mean square (RMS), i.e. proportional to the amplitude
+    Rms,
+
+    /// Mean square, i.e. proportional to the power
+    ///
+    /// The square of the scaled [`Self::Rms`] value, i.e. the energy
+    /// scale is applied before squaring. With the default scale the
+    /// mean square of a full-scale sine wave is `1.0`.
+    MeanSquare,
+
+    /// Mean of the absolute sample values
+    ///
+    /// Less sensitive to isolated peaks than [`Self::Rms`].
+    MeanAbs,
+
+    /// Variance of the sample values, i.e. the mean square without
+    /// the DC offset
+    ///
+    /// The square of the scaled standard deviation, like
+    /// [`Self::MeanSquare`].
+    ///
+    /// Exponential [`EnergyAveraging`] is not supported for this metric
+    /// and for [`Self::MeanAbs`], see [`WaveformFilterConfig::validate()`].
+    /// Creating a [`WaveformFilter`] with this combination panics.
+    Variance,
+}
+
+impl EnergyMetric {
+    pub const DEFAULT: Self = Self::Rms;
+
+    /// Scaled, but not yet squared and clamped energy
+    pub(crate) fn unclamped_energy(
+        self,
+        mean_square: f64,
+        mean_abs: f64,
+        mean: f64,
+        energy_scale: f32,
+    ) -> f32 {
+        match self {
+            Self::Rms | Self::MeanSquare => {
+                unclamped_energy_from_mean_square(mean_square, energy_scale)
+            }
+            #[allow(clippy::cast_possible_truncation)]
+            Self::MeanAbs => (mean_abs * f64::from(energy_scale)) as f32,
+            Self::Variance => unclamped_energy_from_mean_square(
+                (mean_square - mean * mean).max(0.0),
+                energy_scale,
+            ),
+        }
+    }
+
+    const fn supports_exponential_averaging(self) -> bool {
+        matches!(self, Self::Rms | Self::MeanSquare)
+    }
+
+    pub(crate) fn apply(self, bin: &mut FilteredWaveformBinF32) {
+        match self {
+            Self::Rms | Self::MeanAbs => (),
+            Self::MeanSquare | Self::Variance => {
+                let FilteredWaveformBinF32 {
+                    all,
+                    low,
+                    mid,
+                    high,
+                } = bin;
+                for energy in [
+                    &mut all.energy,
+                    &mut low.energy,
+                    &mut mid.energy,
+                    &mut high.energy,
+                ] {
+                    *energy *= *energy;
+                }
+            }
+        }
+    }
+}
+
+impl Default for EnergyMetric {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Scaled, but not yet clamped RMS
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn unclamped_energy_from_mean_square(mean_square: f64, energy_scale: f32) -> f32 {
+    debug_assert!(mean_square >= 0.0);
+    debug_assert!(energy_scale >= 0.0);
+    // The energy needs to be clamped afterwards.
+    (mean_square.sqrt() * f64::from(energy_scale)) as f32
 }
 
 impl WaveformBinAccumulator {
-    fn add_sample(&mut self, sample: f32) {
+    #[must_use]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Number of samples that have been added
+    #[must_use]
+    pub const fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    #[inline]
+    pub fn add_sample(&mut self, sample: f32) {
+        self.add_sample_for_metric(sample, EnergyMetric::DEFAULT);
+    }
+
+    /// Only accumulates the sums that are needed by the energy metric
+    #[inline]
+    fn add_sample_for_metric(&mut self, sample: f32, energy_metric: EnergyMetric) {
+        self.sample_count += 1;
         let sample_f64 = f64::from(sample);
         self.peak = self.peak.max(sample.abs());
-        self.rms_sum += sample_f64 * sample_f64;
+        self.rms_sum.add(sample_f64 * sample_f64);
+        match energy_metric {
+            EnergyMetric::Rms | EnergyMetric::MeanSquare => (),
+            EnergyMetric::MeanAbs => self.abs_sum += sample_f64.abs(),
+            EnergyMetric::Variance => self.sum += sample_f64,
+        }
     }
 
-    fn finish(self, rms_div: f64) -> WaveformBin {
-        debug_assert!(rms_div > 0.0);
-        let Self { peak, rms_sum } = self;
-        // For a sinusoidal signal, the RMS equals `SQRT_2` times the peak
-        // value. This is a good enough approximation of our expected input
-        // signal and we scale and clamp the RMS accordingly.
-        let energy = ((rms_sum / rms_div).sqrt() * std::f64::consts::SQRT_2).min(1.0);
-        #[allow(clippy::cast_possible_truncation)]
-        WaveformBin {
-            peak: WaveformVal::from_f32(peak),
-            energy: WaveformVal::from_f32(energy as f32),
+    /// Finish and quantize the bin
+    ///
+    /// Returns `None` if no samples have been added.
+    #[must_use]
+    pub fn finish(self) -> Option<WaveformBin> {
+        self.finish_unquantized()
+            .as_ref()
+            .map(WaveformBinF32::quantize)
+    }
+
+    /// Same as [`Self::finish()`] but without quantizing the bin
+    #[must_use]
+    pub fn finish_unquantized(self) -> Option<WaveformBinF32> {
+        self.finish_unclamped(DEFAULT_ENERGY_SCALE, EnergyMetric::DEFAULT)
+            .map(WaveformBinF32::clamp_energy)
+    }
+
+    fn finish_unclamped(
+        self,
+        energy_scale: f32,
+        energy_metric: EnergyMetric,
+    ) -> Option<WaveformBinF32> {
+        let Self {
+            sample_count,
+            peak,
+            rms_sum,
+            abs_sum,
+            sum,
+        } = self;
+        if sample_count == 0 {
+            return None;
         }
+        let sample_count = f64::from(sample_count);
+        Some(WaveformBinF32 {
+            peak: peak.min(1.0),
+            energy: energy_metric.unclamped_energy(
+                rms_sum.sum() / sample_count,
+                abs_sum / sample_count,
+                sum / sample_count,
+                energy_scale,
+            ),
+        })
     }
 }
 
 #[derive(Debug, Default)]
-struct FilteredWaveformBinAccumulator {
-    sample_count: u32,
+struct FilteredAccumulators {
     all: WaveformBinAccumulator,
     low: WaveformBinAccumulator,
     mid: WaveformBinAccumulator,
     high: WaveformBinAccumulator,
 }
 
-impl FilteredWaveformBinAccumulator {
-    fn add_sample(&mut self, filter_bank: &mut ThreeBandFilterBank, sample: f32) {
-        self.sample_count += 1;
+impl FilteredAccumulators {
+    const fn sample_count(&self) -> u32 {
+        self.all.sample_count
+    }
+
+    #[inline]
+    fn add_sample(&mut self, sample: FilteredSample, energy_metric: EnergyMetric) {
         let FilteredSample {
             all,
             low,
             mid,
             high,
-        } = filter_bank.run(sample);
-        self.all.add_sample(all);
-        self.low.add_sample(low);
-        self.mid.add_sample(mid);
-        self.high.add_sample(high);
+        } = sample;
+        self.all.add_sample_for_metric(all, energy_metric);
+        self.low.add_sample_for_metric(low, energy_metric);
+        self.mid.add_sample_for_metric(mid, energy_metric);
+        self.high.add_sample_for_metric(high, energy_metric);
     }
 
-    fn finish(self) -> Option<FilteredWaveformBin> {
+    fn finish_unclamped(
+        self,
+        energy_scale: &FilteredEnergyScale,
+        energy_metric: EnergyMetric,
+    ) -> Option<FilteredWaveformBinF32> {
         let Self {
-            sample_count,
             all,
             low,
             mid,
             high,
         } = self;
-        if sample_count == 0 {
-            return None;
-        }
-        let rms_div = f64::from(sample_count);
-        let all = all.finish(rms_div);
-        let low = low.finish(rms_div);
-        let mid = mid.finish(rms_div);
-        let high = high.finish(rms_div);
-        Some(FilteredWaveformBin {
+        let all = all.finish_unclamped(energy_scale.all, energy_metric)?;
+        let low = low.finish_unclamped(energy_scale.low, energy_metric)?;
+        let mid = mid.finish_unclamped(energy_scale.mid, energy_metric)?;
+        let high = high.finish_unclamped(energy_scale.high, energy_metric)?;
+        Some(FilteredWaveformBinF32 {
             all,
             low,
             mid,
@@ -249,79 +704,2304 @@ impl FilteredWaveformBinAccumulator {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct WaveformFilterConfig {
-    pub sample_rate_hz: f32,
-    pub bins_per_sec: f32,
-    pub filter_freqs: ThreeBandFilterFreqConfig,
-}
-
-impl WaveformFilterConfig {
-    pub const DEFAULT: Self = Self {
-        sample_rate_hz: DEFAULT_SAMPLE_RATE_HZ,
-        bins_per_sec: DEFAULT_BINS_PER_SEC,
-        filter_freqs: ThreeBandFilterFreqConfig::DEFAULT,
-    };
-}
-
-impl Default for WaveformFilterConfig {
-    fn default() -> Self {
-        Self::DEFAULT
+pub(crate) fn assert_valid_filter_params(
+    sample_rate_hz: f32,
+    filter_freqs: &ThreeBandFilterFreqConfig,
+) {
+    let ThreeBandFilterFreqConfig {
+        low_lp_hz,
+        low_hp_hz,
+        high_lp_hz,
+        high_hp_hz,
+    } = *filter_freqs;
+    assert!(sample_rate_hz > 0.0, "valid sample rate");
+    for freq_hz in [low_lp_hz, low_hp_hz, high_lp_hz, high_hp_hz] {
+        assert!(freq_hz > 0.0, "valid frequency");
+        assert!(2.0 * freq_hz <= sample_rate_hz, "valid params");
     }
+    assert!(
+        high_lp_hz >= ThreeBandFilterFreqConfig::MIN_MID_BAND_RATIO * low_hp_hz,
+        "valid mid band width"
+    );
 }
 
+/// Filters samples into bands and accumulates them
+///
+/// The building block for custom binning schemes, e.g. one bin per
+/// detected beat. The caller controls the bin boundaries by invoking
+/// [`Self::finish_bin()`]. The state of the filters is preserved across
+/// bin boundaries.
+///
+/// [`WaveformFilter`] uses this accumulator to create bins of uniform size.
 #[derive(Debug)]
-pub struct WaveformFilter {
-    pending_samples_count: f32,
-    samples_per_bin: f32,
+pub struct FilteredWaveformBinAccumulator {
     filter_bank: ThreeBandFilterBank,
-    filtered_accumulator: FilteredWaveformBinAccumulator,
-}
-
-impl Default for WaveformFilter {
-    fn default() -> Self {
-        Self::new(Default::default())
-    }
+    all_source: AllBandSource,
+    energy_metric: EnergyMetric,
+    accumulators: FilteredAccumulators,
 }
 
-impl WaveformFilter {
+impl FilteredWaveformBinAccumulator {
     #[must_use]
     #[allow(clippy::missing_panics_doc)]
-    pub fn new(config: WaveformFilterConfig) -> Self {
-        let WaveformFilterConfig {
-            sample_rate_hz,
-            bins_per_sec,
-            filter_freqs,
-        } = config;
-        let sample_rate = Hertz::<f32>::from_hz(sample_rate_hz).expect("valid sample rate");
-        let samples_per_bin = (sample_rate_hz / bins_per_sec).max(MIN_SAMPLES_PER_BIN);
+    pub fn new(sample_rate_hz: f32, filter_freqs: ThreeBandFilterFreqConfig) -> Self {
+        assert_valid_filter_params(sample_rate_hz, &filter_freqs);
+        let coefficients = ThreeBandFilterCoefficients::new_unchecked(sample_rate_hz, filter_freqs);
+        Self::with_coefficients(
+            Arc::new(coefficients),
+            AllBandSource::DEFAULT,
+            EnergyMetric::DEFAULT,
+        )
+    }
+
+    fn with_coefficients(
+        coefficients: Arc<ThreeBandFilterCoefficients>,
+        all_source: AllBandSource,
+        energy_metric: EnergyMetric,
+    ) -> Self {
         Self {
-            pending_samples_count: 0.0,
-            samples_per_bin,
-            filter_bank: ThreeBandFilterBank::new(sample_rate, filter_freqs),
-            filtered_accumulator: Default::default(),
+            filter_bank: ThreeBandFilterBank::new(coefficients),
+            all_source,
+            energy_metric,
+            accumulators: Default::default(),
         }
     }
 
-    fn finish_bin(&mut self) -> Option<FilteredWaveformBin> {
-        std::mem::take(&mut self.filtered_accumulator).finish()
+    /// Number of samples that have been added to the current bin
+    #[must_use]
+    pub const fn sample_count(&self) -> u32 {
+        self.accumulators.sample_count()
+    }
+
+    #[inline]
+    pub fn add_sample(&mut self, sample: f32) {
+        self.filter_and_add_sample(sample);
     }
 
-    pub fn add_sample(&mut self, sample: f32) -> Option<FilteredWaveformBin> {
-        let next_bin = if self.pending_samples_count >= self.samples_per_bin {
-            self.pending_samples_count -= self.samples_per_bin;
-            self.finish_bin()
-        } else {
-            None
-        };
-        self.filtered_accumulator
-            .add_sample(&mut self.filter_bank, sample);
-        self.pending_samples_count += 1.0;
-        next_bin
+    #[inline]
+    fn filter_sample(&mut self, sample: f32) -> FilteredSample {
+        self.all_source.apply(self.filter_bank.run(sample))
     }
 
-    #[must_use]
-    pub fn finish(mut self) -> Option<FilteredWaveformBin> {
-        self.finish_bin()
+    #[inline]
+    fn filter_and_add_sample(&mut self, sample: f32) -> FilteredSample {
+        let filtered_sample = self.filter_sample(sample);
+        self.accumulators
+            .add_sample(filtered_sample, self.energy_metric);
+        filtered_sample
+    }
+
+    fn take_accumulators(&mut self) -> FilteredAccumulators {
+        std::mem::take(&mut self.accumulators)
+    }
+
+    /// Finish and quantize the current bin and start the next bin
+    ///
+    /// Returns `None` if no samples have been added to the current bin.
+    pub fn finish_bin(&mut self) -> Option<FilteredWaveformBin> {
+        self.finish_bin_unquantized()
+            .as_ref()
+            .map(FilteredWaveformBinF32::quantize)
+    }
+
+    /// Same as [`Self::finish_bin()`] but without quantizing the bin
+    pub fn finish_bin_unquantized(&mut self) -> Option<FilteredWaveformBinF32> {
+        self.take_accumulators()
+            .finish_unclamped(&FilteredEnergyScale::DEFAULT, EnergyMetric::DEFAULT)
+            .map(FilteredWaveformBinF32::clamp_energy)
+    }
+}
+
+/// Decay of held peak values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PeakHoldDecay {
+    /// No peak hold
+    ///
+    /// Each bin reports its own peak value.
+    None,
+
+    /// Held peaks fall linearly by the given amount per second
+    Linear { per_second: f32 },
+
+    /// Held peaks fall by the given number of decibels per second
+    ///
+    /// The ballistics of conventional peak meters, e.g. 20 dB/s.
+    Db { per_second: f32 },
+}
+
+impl PeakHoldDecay {
+    pub const DEFAULT: Self = Self::None;
+}
+
+impl Default for PeakHoldDecay {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Peak hold for each band
+///
+/// Bins report the held peak value if it exceeds their own peak value.
+/// The held peak values decay independently, e.g. to let bass peaks
+/// linger longer than high peaks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilteredPeakHoldConfig {
+    pub all: PeakHoldDecay,
+    pub low: PeakHoldDecay,
+    pub mid: PeakHoldDecay,
+    pub high: PeakHoldDecay,
+}
+
+impl FilteredPeakHoldConfig {
+    pub const DEFAULT: Self = Self {
+        all: PeakHoldDecay::DEFAULT,
+        low: PeakHoldDecay::DEFAULT,
+        mid: PeakHoldDecay::DEFAULT,
+        high: PeakHoldDecay::DEFAULT,
+    };
+}
+
+impl Default for FilteredPeakHoldConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PeakHoldDecayPerBin {
+    None,
+    Linear(f32),
+    Factor(f32),
+}
+
+impl PeakHoldDecayPerBin {
+    fn new(decay: PeakHoldDecay, bin_secs: f32) -> Self {
+        match decay {
+            PeakHoldDecay::None => Self::None,
+            PeakHoldDecay::Linear { per_second } => {
+                debug_assert!(per_second >= 0.0);
+                Self::Linear(per_second * bin_secs)
+            }
+            PeakHoldDecay::Db { per_second } => {
+                debug_assert!(per_second >= 0.0);
+                Self::Factor(10f32.powf(-per_second * bin_secs / 20.0))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PeakHold {
+    decay: PeakHoldDecay,
+    decay_per_bin: PeakHoldDecayPerBin,
+    held_peak: f32,
+}
+
+impl PeakHold {
+    fn new(decay: PeakHoldDecay, bin_secs: f32) -> Self {
+        Self {
+            decay,
+            decay_per_bin: PeakHoldDecayPerBin::new(decay, bin_secs),
+            held_peak: 0.0,
+        }
+    }
+
+    fn set_bin_secs(&mut self, bin_secs: f32) {
+        self.decay_per_bin = PeakHoldDecayPerBin::new(self.decay, bin_secs);
+    }
+
+    fn hold_peak(&mut self, peak: f32) -> f32 {
+        let decayed_peak = match self.decay_per_bin {
+            PeakHoldDecayPerBin::None => {
+                return peak;
+            }
+            PeakHoldDecayPerBin::Linear(decay) => (self.held_peak - decay).max(0.0),
+            PeakHoldDecayPerBin::Factor(factor) => self.held_peak * factor,
+        };
+        self.held_peak = decayed_peak.max(peak);
+        self.held_peak
+    }
+}
+
+#[derive(Debug)]
+struct FilteredPeakHold {
+    all: PeakHold,
+    low: PeakHold,
+    mid: PeakHold,
+    high: PeakHold,
+}
+
+impl FilteredPeakHold {
+    #[allow(clippy::needless_pass_by_value)]
+    fn new(config: FilteredPeakHoldConfig, bin_secs: f32) -> Self {
+        let FilteredPeakHoldConfig {
+            all,
+            low,
+            mid,
+            high,
+        } = config;
+        Self {
+            all: PeakHold::new(all, bin_secs),
+            low: PeakHold::new(low, bin_secs),
+            mid: PeakHold::new(mid, bin_secs),
+            high: PeakHold::new(high, bin_secs),
+        }
+    }
+
+    fn set_bin_secs(&mut self, bin_secs: f32) {
+        self.all.set_bin_secs(bin_secs);
+        self.low.set_bin_secs(bin_secs);
+        self.mid.set_bin_secs(bin_secs);
+        self.high.set_bin_secs(bin_secs);
+    }
+
+    fn hold_peaks(&mut self, accumulator: &mut FilteredAccumulators) {
+        if accumulator.sample_count() == 0 {
+            return;
+        }
+        accumulator.all.peak = self.all.hold_peak(accumulator.all.peak);
+        accumulator.low.peak = self.low.hold_peak(accumulator.low.peak);
+        accumulator.mid.peak = self.mid.hold_peak(accumulator.mid.peak);
+        accumulator.high.peak = self.high.hold_peak(accumulator.high.peak);
+    }
+}
+
+/// Averaging of the squared samples for calculating the energy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EnergyAveraging {
+    /// Mean square of all samples in a bin
+    ///
+    /// The energy of each bin is calculated independently.
+    Block,
+
+    /// Exponentially weighted moving average of the squared samples
+    ///
+    /// The moving average is continuous across bin boundaries and
+    /// sampled at the end of each bin. This results in a smooth energy
+    /// curve without discontinuities between adjacent bins.
+    Exponential {
+        /// Time constant of the moving average
+        time_constant_secs: f32,
+    },
+}
+
+impl EnergyAveraging {
+    pub const DEFAULT: Self = Self::Block;
+}
+
+impl Default for EnergyAveraging {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Debug)]
+struct FilteredMeanSquareEma {
+    alpha: f64,
+    all: f64,
+    low: f64,
+    mid: f64,
+    high: f64,
+}
+
+impl FilteredMeanSquareEma {
+    fn new(time_constant_secs: f32, sample_rate_hz: f32) -> Self {
+        debug_assert!(time_constant_secs > 0.0);
+        let alpha = 1.0 - (-1.0 / f64::from(time_constant_secs * sample_rate_hz)).exp();
+        Self {
+            alpha,
+            all: 0.0,
+            low: 0.0,
+            mid: 0.0,
+            high: 0.0,
+        }
+    }
+
+    fn add_sample(&mut self, sample: FilteredSample) {
+        let Self {
+            alpha,
+            all,
+            low,
+            mid,
+            high,
+        } = self;
+        let FilteredSample {
+            all: all_sample,
+            low: low_sample,
+            mid: mid_sample,
+            high: high_sample,
+        } = sample;
+        for (mean_square, sample) in [
+            (all, all_sample),
+            (low, low_sample),
+            (mid, mid_sample),
+            (high, high_sample),
+        ] {
+            let sample = f64::from(sample);
+            *mean_square += *alpha * (sample * sample - *mean_square);
+        }
+    }
+
+    fn replace_energies(
+        &self,
+        bin: &mut FilteredWaveformBinF32,
+        energy_scale: &FilteredEnergyScale,
+    ) {
+        bin.all.energy = unclamped_energy_from_mean_square(self.all, energy_scale.all);
+        bin.low.energy = unclamped_energy_from_mean_square(self.low, energy_scale.low);
+        bin.mid.energy = unclamped_energy_from_mean_square(self.mid, energy_scale.mid);
+        bin.high.energy = unclamped_energy_from_mean_square(self.high, energy_scale.high);
+    }
+}
+
+/// Configuration of [`WaveformFilter`]
+///
+/// New fields with a default value might be added in the future. Outside
+/// of this crate the config is created from [`Self::DEFAULT`] and then
+/// customized by the `with_*()` builder methods:
+///
+/// ```
+/// use audio_viz::{ThreeBandFilterFreqConfig, WaveformFilterConfig};
+///
+/// const CONFIG: WaveformFilterConfig = WaveformFilterConfig::DEFAULT
+///     .with_sample_rate_hz(48_000.0)
+///     .with_bins_per_sec(100.0)
+///     .with_filter_freqs(ThreeBandFilterFreqConfig::DEFAULT.with_low_lp_hz(220.0))
+///     .with_discard_warmup_bins(4);
+/// assert_eq!(48_000.0, CONFIG.sample_rate_hz);
+/// assert_eq!(220.0, CONFIG.filter_freqs.low_lp_hz);
+/// assert_eq!(4, CONFIG.discard_warmup_bins);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct WaveformFilterConfig {
+    pub sample_rate_hz: f32,
+    pub bins_per_sec: f32,
+    pub filter_freqs: ThreeBandFilterFreqConfig,
+    pub peak_hold: FilteredPeakHoldConfig,
+    pub energy_averaging: EnergyAveraging,
+    pub energy_scale: FilteredEnergyScale,
+    pub energy_metric: EnergyMetric,
+
+    /// Input level in dBFS that maps to half of the full-scale energy
+    ///
+    /// A global calibration of the energies for comparing them across
+    /// tracks, e.g. in a library view. The RMS at the reference level
+    /// is scaled to `0.5` with the default [`Self::energy_scale`],
+    /// i.e. the energy scale of each band is adjusted relative to its
+    /// default, see [`Self::effective_energy_scale()`]. The level refers
+    /// to the samples after applying any gain, e.g. by
+    /// [`WaveformFilter::add_samples_with_gain()`].
+    ///
+    /// The resulting energy depends on the [`Self::energy_metric`]:
+    ///
+    /// - `0.5` for [`EnergyMetric::Rms`]
+    /// - `0.25`, i.e. the square of `0.5`, for [`EnergyMetric::MeanSquare`]
+    /// - `0.25` for [`EnergyMetric::Variance`] without a DC offset
+    /// - for [`EnergyMetric::MeanAbs`] the ratio between mean absolute value
+    ///   and RMS times `0.5`, e.g. about `0.45` for a sinusoidal signal
+    ///
+    /// If `None` then [`Self::energy_scale`] is applied as is.
+    pub energy_reference_db: Option<f32>,
+
+    pub all_source: AllBandSource,
+
+    /// Number of leading bins that are discarded
+    ///
+    /// The filters need some time to settle. The first bins of a stream
+    /// might contain transients that are caused by the filters and not
+    /// by the signal. These bins are processed, but not emitted.
+    pub discard_warmup_bins: usize,
+}
+
+fn validate_bins_per_sec(bins_per_sec: f32) -> Result<(), WaveformFilterConfigError> {
+    if !bins_per_sec.is_finite() || bins_per_sec <= 0.0 {
+        return Err(WaveformFilterConfigError::InvalidBinsPerSec { bins_per_sec });
+    }
+    Ok(())
+}
+
+fn samples_per_bin(sample_rate_hz: f32, bins_per_sec: f32) -> f32 {
+    (sample_rate_hz / bins_per_sec).max(MIN_SAMPLES_PER_BIN)
+}
+
+/// Bin boundaries at exact sample positions
+///
+/// Bin `k` ends at the first sample at or after the fractional position
+/// `(k + 1) * samples_per_bin`. The positions are calculated from integer
+/// counters instead of accumulating the fractional number of samples
+/// per bin, i.e. rounding errors do not accumulate and the boundaries
+/// never drift apart from their ideal positions.
+#[derive(Debug, Clone)]
+pub(crate) struct BinBoundaries {
+    samples_per_bin: f32,
+    first_bin_offset: f64,
+    samples_count: u64,
+    bins_count: u64,
+    next_bin_end: u64,
+}
+
+impl BinBoundaries {
+    pub(crate) fn new(samples_per_bin: f32) -> Self {
+        Self::with_first_bin_offset(samples_per_bin, 0.0)
+    }
+
+    fn with_first_bin_offset(samples_per_bin: f32, first_bin_offset: f64) -> Self {
+        let mut boundaries = Self {
+            samples_per_bin,
+            first_bin_offset,
+            samples_count: 0,
+            bins_count: 0,
+            next_bin_end: 0,
+        };
+        boundaries.next_bin_end = boundaries.bin_end(0);
+        boundaries
+    }
+
+    pub(crate) const fn samples_per_bin(&self) -> f32 {
+        self.samples_per_bin
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_precision_loss)]
+    #[allow(clippy::cast_sign_loss)]
+    fn bin_end(&self, bin_index: u64) -> u64 {
+        ((bin_index + 1) as f64 * f64::from(self.samples_per_bin) - self.first_bin_offset).ceil()
+            as u64
+    }
+
+    /// Shorten the first bin by the given number of samples
+    ///
+    /// Must be invoked before adding the first sample.
+    pub(crate) fn shorten_first_bin(&mut self, samples_count: f32) {
+        debug_assert_eq!(0, self.samples_count);
+        debug_assert!(samples_count < self.samples_per_bin);
+        *self = Self::with_first_bin_offset(self.samples_per_bin, f64::from(samples_count));
+    }
+
+    /// Restart with a new number of samples per bin
+    pub(crate) fn restart(&mut self, samples_per_bin: f32) {
+        *self = Self::new(samples_per_bin);
+    }
+
+    /// Check if the next sample starts a new bin
+    pub(crate) const fn is_bin_complete(&self) -> bool {
+        self.samples_count >= self.next_bin_end
+    }
+
+    /// Start the next bin after [`Self::is_bin_complete()`]
+    pub(crate) fn start_next_bin(&mut self) {
+        debug_assert!(self.is_bin_complete());
+        self.bins_count += 1;
+        self.next_bin_end = self.bin_end(self.bins_count);
+    }
+
+    pub(crate) fn add_sample(&mut self) {
+        self.samples_count += 1;
+    }
+
+    /// Number of bins that are completed when adding more samples
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_precision_loss)]
+    #[allow(clippy::cast_sign_loss)]
+    pub(crate) fn bin_count_for_samples(&self, sample_count: usize) -> usize {
+        if sample_count == 0 {
+            return 0;
+        }
+        // Bin `k` is completed when adding the sample after its end.
+        let last_sample_index = self.samples_count + sample_count as u64 - 1;
+        let mut completed_bins_count = ((last_sample_index as f64 + self.first_bin_offset)
+            / f64::from(self.samples_per_bin))
+        .floor() as u64;
+        // Compensate rounding errors at the boundaries
+        while completed_bins_count > 0 && self.bin_end(completed_bins_count - 1) > last_sample_index
+        {
+            completed_bins_count -= 1;
+        }
+        while self.bin_end(completed_bins_count) <= last_sample_index {
+            completed_bins_count += 1;
+        }
+        completed_bins_count.saturating_sub(self.bins_count) as usize
+    }
+}
+
+impl WaveformFilterConfig {
+    pub const DEFAULT: Self = Self {
+        sample_rate_hz: DEFAULT_SAMPLE_RATE_HZ,
+        bins_per_sec: DEFAULT_BINS_PER_SEC,
+        filter_freqs: ThreeBandFilterFreqConfig::DEFAULT,
+        peak_hold: FilteredPeakHoldConfig::DEFAULT,
+        energy_averaging: EnergyAveraging::DEFAULT,
+        energy_scale: FilteredEnergyScale::DEFAULT,
+        energy_metric: EnergyMetric::DEFAULT,
+        energy_reference_db: None,
+        all_source: AllBandSource::DEFAULT,
+        discard_warmup_bins: 0,
+    };
+
+    /// Replace [`Self::sample_rate_hz`]
+    #[must_use]
+    pub const fn with_sample_rate_hz(self, sample_rate_hz: f32) -> Self {
+        Self {
+            sample_rate_hz,
+            ..self
+        }
+    }
+
+    /// Replace [`Self::bins_per_sec`]
+    #[must_use]
+    pub const fn with_bins_per_sec(self, bins_per_sec: f32) -> Self {
+        Self {
+            bins_per_sec,
+            ..self
+        }
+    }
+
+    /// Replace [`Self::filter_freqs`]
+    #[must_use]
+    pub const fn with_filter_freqs(self, filter_freqs: ThreeBandFilterFreqConfig) -> Self {
+        Self {
+            filter_freqs,
+            ..self
+        }
+    }
+
+    /// Replace [`Self::peak_hold`]
+    #[must_use]
+    pub const fn with_peak_hold(self, peak_hold: FilteredPeakHoldConfig) -> Self {
+        Self { peak_hold, ..self }
+    }
+
+    /// Replace [`Self::energy_averaging`]
+    #[must_use]
+    pub const fn with_energy_averaging(self, energy_averaging: EnergyAveraging) -> Self {
+        Self {
+            energy_averaging,
+            ..self
+        }
+    }
+
+    /// Replace [`Self::energy_scale`]
+    #[must_use]
+    pub const fn with_energy_scale(self, energy_scale: FilteredEnergyScale) -> Self {
+        Self {
+            energy_scale,
+            ..self
+        }
+    }
+
+    /// Replace [`Self::energy_metric`]
+    #[must_use]
+    pub const fn with_energy_metric(self, energy_metric: EnergyMetric) -> Self {
+        Self {
+            energy_metric,
+            ..self
+        }
+    }
+
+    /// Replace [`Self::energy_reference_db`]
+    #[must_use]
+    pub const fn with_energy_reference_db(self, energy_reference_db: Option<f32>) -> Self {
+        Self {
+            energy_reference_db,
+            ..self
+        }
+    }
+
+    /// Replace [`Self::all_source`]
+    #[must_use]
+    pub const fn with_all_source(self, all_source: AllBandSource) -> Self {
+        Self { all_source, ..self }
+    }
+
+    /// Replace [`Self::discard_warmup_bins`]
+    #[must_use]
+    pub const fn with_discard_warmup_bins(self, discard_warmup_bins: usize) -> Self {
+        Self {
+            discard_warmup_bins,
+            ..self
+        }
+    }
+
+    /// Check all parameters
+    ///
+    /// Returns the first violation, checking the sample rate, the number
+    /// of bins per second, the range and order of the crossover frequencies,
+    /// the Nyquist frequency, the width of the mid band, the energy averaging
+    /// and its combination with the energy metric, the peak hold decays,
+    /// the energy scales, and the energy reference level in this order.
+    /// A valid config never panics when creating a [`WaveformFilter`],
+    /// e.g. for validating a config that has been edited in a UI.
+    pub fn validate(&self) -> Result<(), WaveformFilterConfigError> {
+        let Self {
+            sample_rate_hz,
+            bins_per_sec,
+            filter_freqs,
+            peak_hold,
+            energy_averaging,
+            energy_scale,
+            energy_metric,
+            energy_reference_db,
+            all_source: _,
+            discard_warmup_bins: _,
+        } = self;
+        let sample_rate_hz = *sample_rate_hz;
+        let bins_per_sec = *bins_per_sec;
+        if !sample_rate_hz.is_finite() || sample_rate_hz <= 0.0 {
+            return Err(WaveformFilterConfigError::InvalidSampleRate { sample_rate_hz });
+        }
+        validate_bins_per_sec(bins_per_sec)?;
+        let ThreeBandFilterFreqConfig {
+            low_lp_hz,
+            low_hp_hz,
+            high_lp_hz,
+            high_hp_hz,
+        } = *filter_freqs;
+        let freqs_hz = [low_lp_hz, low_hp_hz, high_lp_hz, high_hp_hz];
+        if let Some(freq_hz) = freqs_hz.into_iter().find(|freq_hz| {
+            !(ThreeBandFilterFreqConfig::MIN_FREQ_HZ..=ThreeBandFilterFreqConfig::MAX_FREQ_HZ)
+                .contains(freq_hz)
+        }) {
+            return Err(WaveformFilterConfigError::FrequencyOutOfRange { freq_hz });
+        }
+        if !(low_hp_hz <= low_lp_hz && low_lp_hz < high_hp_hz && high_hp_hz <= high_lp_hz) {
+            return Err(WaveformFilterConfigError::InvalidCrossoverOrder);
+        }
+        let nyquist_hz = sample_rate_hz / 2.0;
+        if let Some(freq_hz) = freqs_hz.into_iter().find(|&freq_hz| freq_hz > nyquist_hz) {
+            return Err(WaveformFilterConfigError::AboveNyquist {
+                freq_hz,
+                nyquist_hz,
+            });
+        }
+        if high_lp_hz < ThreeBandFilterFreqConfig::MIN_MID_BAND_RATIO * low_hp_hz {
+            return Err(WaveformFilterConfigError::MidBandTooNarrow {
+                low_hp_hz,
+                high_lp_hz,
+            });
+        }
+        if let EnergyAveraging::Exponential { time_constant_secs } = *energy_averaging {
+            if !time_constant_secs.is_finite() || time_constant_secs <= 0.0 {
+                return Err(WaveformFilterConfigError::InvalidEnergyTimeConstant {
+                    time_constant_secs,
+                });
+            }
+            if !energy_metric.supports_exponential_averaging() {
+                return Err(WaveformFilterConfigError::UnsupportedEnergyAveraging {
+                    energy_metric: *energy_metric,
+                });
+            }
+        }
+        let FilteredPeakHoldConfig {
+            all,
+            low,
+            mid,
+            high,
+        } = *peak_hold;
+        if let Some(per_second) = [all, low, mid, high]
+            .into_iter()
+            .filter_map(|decay| match decay {
+                PeakHoldDecay::None => None,
+                PeakHoldDecay::Linear { per_second } | PeakHoldDecay::Db { per_second } => {
+                    Some(per_second)
+                }
+            })
+            .find(|per_second| !per_second.is_finite() || *per_second < 0.0)
+        {
+            return Err(WaveformFilterConfigError::InvalidPeakHoldDecay { per_second });
+        }
+        let FilteredEnergyScale {
+            all,
+            low,
+            mid,
+            high,
+        } = *energy_scale;
+        if let Some(energy_scale) = [all, low, mid, high]
+            .into_iter()
+            .find(|energy_scale| !energy_scale.is_finite() || *energy_scale < 0.0)
+        {
+            return Err(WaveformFilterConfigError::InvalidEnergyScale { energy_scale });
+        }
+        if let Some(energy_reference_db) = *energy_reference_db {
+            if !energy_reference_db.is_finite()
+                || !energy_reference_factor(energy_reference_db).is_finite()
+            {
+                return Err(WaveformFilterConfigError::InvalidEnergyReference {
+                    energy_reference_db,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The energy scale after applying [`Self::energy_reference_db`]
+    #[must_use]
+    pub fn effective_energy_scale(&self) -> FilteredEnergyScale {
+        let Some(energy_reference_db) = self.energy_reference_db else {
+            return self.energy_scale.clone();
+        };
+        let factor = energy_reference_factor(energy_reference_db);
+        let FilteredEnergyScale {
+            all,
+            low,
+            mid,
+            high,
+        } = self.energy_scale;
+        FilteredEnergyScale {
+            all: all * factor,
+            low: low * factor,
+            mid: mid * factor,
+            high: high * factor,
+        }
+    }
+
+    /// Check if the config equals [`Self::DEFAULT`]
+    ///
+    /// All `f32` parameters are compared exactly, i.e. values that only
+    /// approximately match the defaults, e.g. after a round trip through
+    /// a UI control, are considered as different.
+    #[must_use]
+    pub fn is_default(&self) -> bool {
+        *self == Self::DEFAULT
+    }
+
+    pub fn reset_to_default(&mut self) {
+        *self = Self::DEFAULT;
+    }
+
+    /// Adjust the config for a different sample rate
+    ///
+    /// The crossover frequencies and the number of bins per second are
+    /// preserved. The filter coefficients are calculated for the actual
+    /// sample rate when creating a [`WaveformFilter`] from the resulting
+    /// config. This allows to use a single preset for inputs with
+    /// different sample rates.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first violation of the resulting config, see
+    /// [`Self::validate()`], e.g. if the crossover frequencies exceed the
+    /// Nyquist frequency of a low sample rate.
+    pub fn retuned_for(
+        &self,
+        actual_sample_rate_hz: f32,
+    ) -> Result<Self, WaveformFilterConfigError> {
+        let config = Self {
+            sample_rate_hz: actual_sample_rate_hz,
+            ..self.clone()
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Number of samples per bin
+    ///
+    /// Limited by a lower bound to prevent bins that are too short.
+    #[must_use]
+    pub fn samples_per_bin(&self) -> f32 {
+        samples_per_bin(self.sample_rate_hz, self.bins_per_sec)
+    }
+
+    /// Number of samples per bin with an upper bound on the number of bins
+    ///
+    /// The number of samples per bin is increased such that `sample_count`
+    /// samples result in at most `max_bins` bins.
+    #[must_use]
+    pub fn bounded_samples_per_bin(&self, sample_count: usize, max_bins: NonZeroUsize) -> f32 {
+        #[allow(clippy::cast_precision_loss)]
+        #[allow(clippy::cast_possible_truncation)]
+        let min_samples_per_bin = (sample_count as f64 / max_bins.get() as f64).ceil() as f32;
+        self.samples_per_bin().max(min_samples_per_bin)
+    }
+}
+
+impl Default for WaveformFilterConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Receiver of completed bins
+///
+/// Decouples [`WaveformFilter`] from any particular collection type.
+pub trait BinSink {
+    /// Receive the next completed bin
+    ///
+    /// Bins are pushed in order, i.e. `index` is strictly increasing
+    /// and counts all bins that have been completed by the filter.
+    fn push_bin(&mut self, index: usize, bin: &FilteredWaveformBin);
+}
+
+impl BinSink for Vec<FilteredWaveformBin> {
+    fn push_bin(&mut self, _index: usize, bin: &FilteredWaveformBin) {
+        self.push(bin.clone());
+    }
+}
+
+/// Insufficient capacity of an output buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityExceeded {
+    /// Number of bins that need to be stored
+    pub required: usize,
+
+    /// Number of bins that could be stored
+    pub capacity: usize,
+}
+
+impl std::fmt::Display for CapacityExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { required, capacity } = self;
+        write!(
+            f,
+            "capacity exceeded: {required} bins required, {capacity} bins available"
+        )
+    }
+}
+
+impl std::error::Error for CapacityExceeded {}
+
+/// Invalid parameter of a [`WaveformFilterConfig`]
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum WaveformFilterConfigError {
+    /// The sample rate is not a positive, finite number
+    InvalidSampleRate { sample_rate_hz: f32 },
+
+    /// The number of bins per second is not a positive, finite number
+    InvalidBinsPerSec { bins_per_sec: f32 },
+
+    /// A crossover frequency is outside of the range from
+    /// [`ThreeBandFilterFreqConfig::MIN_FREQ_HZ`] to
+    /// [`ThreeBandFilterFreqConfig::MAX_FREQ_HZ`]
+    FrequencyOutOfRange { freq_hz: f32 },
+
+    /// The crossover frequencies are not ordered as
+    /// `low_hp_hz <= low_lp_hz < high_hp_hz <= high_lp_hz`
+    InvalidCrossoverOrder,
+
+    /// A crossover frequency exceeds the Nyquist frequency
+    AboveNyquist { freq_hz: f32, nyquist_hz: f32 },
+
+    /// The mid band is narrower than
+    /// [`ThreeBandFilterFreqConfig::MIN_MID_BAND_RATIO`]
+    MidBandTooNarrow { low_hp_hz: f32, high_lp_hz: f32 },
+
+    /// Exponential [`EnergyAveraging`] is not supported by the
+    /// [`EnergyMetric`]
+    UnsupportedEnergyAveraging { energy_metric: EnergyMetric },
+
+    /// The time constant of the exponential [`EnergyAveraging`] is not
+    /// a positive, finite number
+    InvalidEnergyTimeConstant { time_constant_secs: f32 },
+
+    /// The decay of a [`PeakHoldDecay`] is not a non-negative, finite
+    /// number
+    InvalidPeakHoldDecay { per_second: f32 },
+
+    /// The scale of a band in [`FilteredEnergyScale`] is not
+    /// a non-negative, finite number
+    InvalidEnergyScale { energy_scale: f32 },
+
+    /// The [`WaveformFilterConfig::energy_reference_db`] is not finite
+    /// or too low
+    InvalidEnergyReference { energy_reference_db: f32 },
+}
+
+impl std::fmt::Display for WaveformFilterConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSampleRate { sample_rate_hz } => {
+                write!(f, "invalid sample rate: {sample_rate_hz} Hz")
+            }
+            Self::InvalidBinsPerSec { bins_per_sec } => {
+                write!(f, "invalid number of bins per second: {bins_per_sec}")
+            }
+            Self::FrequencyOutOfRange { freq_hz } => {
+                write!(f, "crossover frequency out of range: {freq_hz} Hz")
+            }
+            Self::InvalidCrossoverOrder => f.write_str("invalid order of crossover frequencies"),
+            Self::AboveNyquist {
+                freq_hz,
+                nyquist_hz,
+            } => write!(
+                f,
+                "crossover frequency {freq_hz} Hz above Nyquist frequency {nyquist_hz} Hz"
+            ),
+            Self::MidBandTooNarrow {
+                low_hp_hz,
+                high_lp_hz,
+            } => write!(f, "mid band too narrow: {low_hp_hz} Hz to {high_lp_hz} Hz"),
+            Self::UnsupportedEnergyAveraging { energy_metric } => write!(
+                f,
+                "exponential energy averaging not supported for {energy_metric:?}"
+            ),
+            Self::InvalidEnergyTimeConstant { time_constant_secs } => write!(
+                f,
+                "invalid time constant of the energy averaging: {time_constant_secs} s"
+            ),
+            Self::InvalidPeakHoldDecay { per_second } => {
+                write!(f, "invalid peak hold decay: {per_second} per second")
+            }
+            Self::InvalidEnergyScale { energy_scale } => {
+                write!(f, "invalid energy scale: {energy_scale}")
+            }
+            Self::InvalidEnergyReference {
+                energy_reference_db,
+            } => write!(
+                f,
+                "invalid energy reference level: {energy_reference_db} dB"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WaveformFilterConfigError {}
+
+#[derive(Debug)]
+pub struct WaveformFilter {
+    sample_rate_hz: f32,
+    warmup_bins_count: usize,
+    completed_bins_count: usize,
+    added_samples_count: u64,
+    bin_boundaries: BinBoundaries,
+    accumulator: FilteredWaveformBinAccumulator,
+    max_unclamped_energy: f32,
+    peak_hold: FilteredPeakHold,
+    mean_square_ema: Option<FilteredMeanSquareEma>,
+    energy_scale: FilteredEnergyScale,
+    energy_metric: EnergyMetric,
+}
+
+impl Default for WaveformFilter {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl WaveformFilter {
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn new(config: WaveformFilterConfig) -> Self {
+        let samples_per_bin = config.samples_per_bin();
+        Self::with_samples_per_bin(config, samples_per_bin)
+    }
+
+    /// Create a new filter without checking the config
+    ///
+    /// Intended for configs that are known to be valid, e.g. when
+    /// recreating filters repeatedly with the same config. The sample rate
+    /// must be positive and all crossover frequencies must be positive and
+    /// must not exceed the Nyquist frequency. Otherwise the filter output
+    /// is unspecified.
+    ///
+    /// Consistency checks may still be performed in debug builds.
+    #[must_use]
+    pub fn new_unchecked(config: WaveformFilterConfig) -> Self {
+        let samples_per_bin = config.samples_per_bin();
+        Self::with_samples_per_bin_unchecked(config, samples_per_bin)
+    }
+
+    /// Create a new filter with shared, precomputed coefficients
+    ///
+    /// Avoids recalculating the coefficients when creating many filters
+    /// with the same sample rate and crossover frequencies. Only the
+    /// mutable state of the filters is allocated per instance.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sample rate or the crossover frequencies of the
+    /// coefficients differ from those in `config` or if the energy metric
+    /// does not support the energy averaging, see
+    /// [`WaveformFilterConfig::validate()`].
+    #[must_use]
+    #[allow(clippy::float_cmp)] // Identical config values
+    pub fn with_coefficients(
+        config: WaveformFilterConfig,
+        coefficients: Arc<ThreeBandFilterCoefficients>,
+    ) -> Self {
+        assert!(
+            config.sample_rate_hz == coefficients.sample_rate_hz()
+                && config.filter_freqs == *coefficients.filter_freqs(),
+            "matching coefficients"
+        );
+        let samples_per_bin = config.samples_per_bin();
+        Self::with_samples_per_bin_and_coefficients(config, samples_per_bin, coefficients)
+    }
+
+    pub(crate) fn with_samples_per_bin(config: WaveformFilterConfig, samples_per_bin: f32) -> Self {
+        assert_valid_filter_params(config.sample_rate_hz, &config.filter_freqs);
+        Self::with_samples_per_bin_unchecked(config, samples_per_bin)
+    }
+
+    fn with_samples_per_bin_unchecked(config: WaveformFilterConfig, samples_per_bin: f32) -> Self {
+        let coefficients = ThreeBandFilterCoefficients::new_unchecked(
+            config.sample_rate_hz,
+            config.filter_freqs.clone(),
+        );
+        Self::with_samples_per_bin_and_coefficients(config, samples_per_bin, Arc::new(coefficients))
+    }
+
+    fn with_samples_per_bin_and_coefficients(
+        config: WaveformFilterConfig,
+        samples_per_bin: f32,
+        coefficients: Arc<ThreeBandFilterCoefficients>,
+    ) -> Self {
+        debug_assert!(samples_per_bin >= MIN_SAMPLES_PER_BIN);
+        let energy_scale = config.effective_energy_scale();
+        let WaveformFilterConfig {
+            sample_rate_hz,
+            bins_per_sec: _,
+            filter_freqs: _,
+            peak_hold,
+            energy_averaging,
+            energy_scale: _,
+            energy_metric,
+            energy_reference_db: _,
+            all_source,
+            discard_warmup_bins,
+        } = config;
+        let bin_secs = samples_per_bin / sample_rate_hz;
+        Self {
+            sample_rate_hz,
+            warmup_bins_count: discard_warmup_bins,
+            completed_bins_count: 0,
+            added_samples_count: 0,
+            bin_boundaries: BinBoundaries::new(samples_per_bin),
+            accumulator: FilteredWaveformBinAccumulator::with_coefficients(
+                coefficients,
+                all_source,
+                energy_metric,
+            ),
+            max_unclamped_energy: 0.0,
+            peak_hold: FilteredPeakHold::new(peak_hold, bin_secs),
+            mean_square_ema: match energy_averaging {
+                EnergyAveraging::Block => None,
+                EnergyAveraging::Exponential { time_constant_secs } => {
+                    assert!(
+                        energy_metric.supports_exponential_averaging(),
+                        "supported energy averaging"
+                    );
+                    Some(FilteredMeanSquareEma::new(
+                        time_constant_secs,
+                        sample_rate_hz,
+                    ))
+                }
+            },
+            energy_scale,
+            energy_metric,
+        }
+    }
+
+    /// Change the number of bins per second
+    ///
+    /// Finishes and returns the current, partial bin. All following bins
+    /// use the new number of samples per bin, i.e. bins before and after
+    /// the change have different durations. The state of the filters is
+    /// preserved, e.g. for switching between a fine resolution during
+    /// playback and a coarse resolution while scrubbing.
+    ///
+    /// Invalid values are rejected like by [`WaveformFilterConfig::validate()`]
+    /// without modifying the filter.
+    pub fn set_bins_per_sec(
+        &mut self,
+        bins_per_sec: f32,
+    ) -> Result<Option<FilteredWaveformBin>, WaveformFilterConfigError> {
+        validate_bins_per_sec(bins_per_sec)?;
+        let partial_bin = self.finish_bin();
+        if partial_bin.is_some() {
+            self.completed_bins_count += 1;
+        }
+        self.bin_boundaries
+            .restart(samples_per_bin(self.sample_rate_hz, bins_per_sec));
+        self.peak_hold
+            .set_bin_secs(self.bin_boundaries.samples_per_bin() / self.sample_rate_hz);
+        Ok(partial_bin.as_ref().map(FilteredWaveformBinF32::quantize))
+    }
+
+    /// Settle the state of the filters without accumulating any bins
+    ///
+    /// Must be invoked before adding the first sample.
+    pub(crate) fn warm_up(&mut self, samples: &[f32]) {
+        debug_assert_eq!(0, self.accumulator.sample_count());
+        for &sample in samples {
+            let filtered_sample = self.accumulator.filter_sample(sample);
+            if let Some(mean_square_ema) = &mut self.mean_square_ema {
+                mean_square_ema.add_sample(filtered_sample);
+            }
+        }
+    }
+
+    pub(crate) const fn sample_rate_hz(&self) -> f32 {
+        self.sample_rate_hz
+    }
+
+    /// Shorten the first bin by the given number of samples
+    ///
+    /// Shifts the phase of all bin boundaries. Must be invoked before
+    /// adding the first sample.
+    pub(crate) fn shorten_first_bin(&mut self, samples_count: f32) {
+        debug_assert_eq!(0, self.accumulator.sample_count());
+        self.bin_boundaries.shorten_first_bin(samples_count);
+    }
+
+    fn finish_bin(&mut self) -> Option<FilteredWaveformBinF32> {
+        let mut accumulators = self.accumulator.take_accumulators();
+        if accumulators.sample_count() == 0 {
+            return None;
+        }
+        if self.warmup_bins_count > 0 {
+            // Discarded before holding their peaks, i.e. the unsettled
+            // peaks do not affect subsequent bins
+            self.warmup_bins_count -= 1;
+            return None;
+        }
+        self.peak_hold.hold_peaks(&mut accumulators);
+        let mut bin = accumulators.finish_unclamped(&self.energy_scale, self.energy_metric)?;
+        if let Some(mean_square_ema) = &self.mean_square_ema {
+            mean_square_ema.replace_energies(&mut bin, &self.energy_scale);
+        }
+        self.energy_metric.apply(&mut bin);
+        self.max_unclamped_energy = self.max_unclamped_energy.max(bin.max_energy());
+        Some(bin.clamp_energy())
+    }
+
+    #[inline]
+    pub fn add_sample(&mut self, sample: f32) -> Option<FilteredWaveformBin> {
+        self.add_sample_unquantized(sample)
+            .as_ref()
+            .map(FilteredWaveformBinF32::quantize)
+    }
+
+    /// Check if the next sample starts a new bin
+    ///
+    /// The current bin is then finished, even if it is discarded.
+    pub(crate) const fn is_bin_complete(&self) -> bool {
+        self.bin_boundaries.is_bin_complete()
+    }
+
+    /// Same as [`Self::add_sample()`] but without quantizing the bin
+    #[inline]
+    pub fn add_sample_unquantized(&mut self, sample: f32) -> Option<FilteredWaveformBinF32> {
+        self.add_sample_unquantized_filtered(sample).0
+    }
+
+    /// Same as [`Self::add_sample()`] but also returns the filtered sample
+    #[inline]
+    pub(crate) fn add_sample_filtered(
+        &mut self,
+        sample: f32,
+    ) -> (Option<FilteredWaveformBin>, FilteredSample) {
+        let (next_bin, filtered_sample) = self.add_sample_unquantized_filtered(sample);
+        (
+            next_bin.as_ref().map(FilteredWaveformBinF32::quantize),
+            filtered_sample,
+        )
+    }
+
+    #[inline]
+    fn add_sample_unquantized_filtered(
+        &mut self,
+        sample: f32,
+    ) -> (Option<FilteredWaveformBinF32>, FilteredSample) {
+        let next_bin = if self.is_bin_complete() {
+            self.bin_boundaries.start_next_bin();
+            let next_bin = self.finish_bin();
+            if next_bin.is_some() {
+                self.completed_bins_count += 1;
+            }
+            next_bin
+        } else {
+            None
+        };
+        let filtered_sample = self.accumulator.filter_and_add_sample(sample);
+        if let Some(mean_square_ema) = &mut self.mean_square_ema {
+            mean_square_ema.add_sample(filtered_sample);
+        }
+        self.added_samples_count += 1;
+        self.bin_boundaries.add_sample();
+        (next_bin, filtered_sample)
+    }
+
+    /// Number of bins that are completed when adding more samples
+    ///
+    /// Returns the exact number of bins that would be returned by
+    /// [`Self::add_sample()`] or pushed by [`Self::process_into()`]
+    /// for the given number of samples. Could be used for sizing
+    /// output buffers in advance. The trailing bin that is returned
+    /// by [`Self::finish()`] is not included.
+    #[must_use]
+    pub fn bin_count_for_samples(&self, sample_count: usize) -> usize {
+        self.bin_boundaries
+            .bin_count_for_samples(sample_count)
+            .saturating_sub(self.warmup_bins_count)
+    }
+
+    /// Maximum energy of all bands before clamping
+    ///
+    /// A calibration aid that returns the maximum of the scaled RMS values
+    /// of all bands and all bins that have been completed so far. Values
+    /// greater than `1.0` are clamped in the resulting bins.
+    #[must_use]
+    pub const fn max_unclamped_energy(&self) -> f32 {
+        self.max_unclamped_energy
+    }
+
+    /// Coefficients of all biquad filters
+    ///
+    /// Returns the normalized coefficients `[a1, a2, b0, b1, b2]` (with `a0 = 1`)
+    /// of the six 2nd-order filter stages in the following order:
+    ///
+    /// 1. Low band, 1st low pass (low/mid crossover)
+    /// 2. Low band, 2nd low pass (low/mid crossover)
+    /// 3. Mid band, high pass (low/mid crossover)
+    /// 4. Mid band, low pass (mid/high crossover)
+    /// 5. High band, 1st high pass (mid/high crossover)
+    /// 6. High band, 2nd high pass (mid/high crossover)
+    ///
+    /// The stages of each band are applied in this order.
+    #[must_use]
+    pub fn filter_coefficients(&self) -> [[f32; 5]; 6] {
+        self.accumulator.filter_bank.coefficients.to_arrays()
+    }
+
+    /// Number of samples that have been added
+    ///
+    /// Samples that have only been used for warming up the filters
+    /// are not counted.
+    #[must_use]
+    pub const fn added_samples_count(&self) -> u64 {
+        self.added_samples_count
+    }
+
+    /// Duration of all samples that have been added
+    ///
+    /// See also [`Self::added_samples_count()`].
+    #[must_use]
+    pub fn elapsed_secs(&self) -> f32 {
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_precision_loss)]
+        let elapsed_secs =
+            (self.added_samples_count as f64 / f64::from(self.sample_rate_hz)) as f32;
+        elapsed_secs
+    }
+
+    /// Process a slice of samples and push all completed bins into `sink`
+    ///
+    /// The trailing, incomplete bin is still pending afterwards and
+    /// must be obtained by calling [`Self::finish()`].
+    pub fn process_into(&mut self, samples: &[f32], sink: &mut impl BinSink) {
+        for &sample in samples {
+            let index = self.completed_bins_count;
+            if let Some(bin) = self.add_sample(sample) {
+                sink.push_bin(index, &bin);
+            }
+        }
+    }
+
+    /// Same as [`Self::process_into()`] but applies a gain to each sample
+    /// before filtering
+    ///
+    /// The gain is a function of the absolute index of the sample, see
+    /// [`Self::added_samples_count()`], e.g. for previewing a volume
+    /// automation curve with fades.
+    pub fn add_samples_with_gain(
+        &mut self,
+        samples: &[f32],
+        gain: impl Fn(u64) -> f32,
+        sink: &mut impl BinSink,
+    ) {
+        for &sample in samples {
+            let index = self.completed_bins_count;
+            let sample = sample * gain(self.added_samples_count);
+            if let Some(bin) = self.add_sample(sample) {
+                sink.push_bin(index, &bin);
+            }
+        }
+    }
+
+    /// Process a slice of samples and pass all completed bins to a callback
+    ///
+    /// Returns the number of bins that have been completed, e.g. for
+    /// bookkeeping when the bins are stored elsewhere. The trailing,
+    /// incomplete bin is still pending afterwards.
+    pub fn add_samples_count(
+        &mut self,
+        samples: &[f32],
+        mut sink: impl FnMut(FilteredWaveformBin),
+    ) -> usize {
+        let mut bin_count = 0;
+        for &sample in samples {
+            if let Some(bin) = self.add_sample(sample) {
+                sink(bin);
+                bin_count += 1;
+            }
+        }
+        bin_count
+    }
+
+    /// Process a slice of samples and store all completed bins into a buffer
+    ///
+    /// Never allocates and is suitable for real-time threads, e.g. with
+    /// bounded, stack-allocated storage. Returns the number of bins
+    /// that have been stored at the start of `out`.
+    ///
+    /// Fails without processing any samples if the capacity of `out`
+    /// is insufficient, see also [`Self::bin_count_for_samples()`].
+    pub fn process_into_slice(
+        &mut self,
+        samples: &[f32],
+        out: &mut [FilteredWaveformBin],
+    ) -> Result<usize, CapacityExceeded> {
+        let required = self.bin_count_for_samples(samples.len());
+        if required > out.len() {
+            return Err(CapacityExceeded {
+                required,
+                capacity: out.len(),
+            });
+        }
+        let mut bin_count = 0;
+        for &sample in samples {
+            if let Some(bin) = self.add_sample(sample) {
+                out[bin_count] = bin;
+                bin_count += 1;
+            }
+        }
+        debug_assert_eq!(required, bin_count);
+        Ok(bin_count)
+    }
+
+    /// Process a slice of samples in chunks and report the progress
+    ///
+    /// Same as [`Self::process_into()`], but invokes `on_progress` after
+    /// each chunk of (at most) `chunk_len` samples with the fraction of
+    /// samples that have been processed so far. The last invocation
+    /// reports `1.0`, i.e. `on_progress` is invoked once even if
+    /// `samples` is empty.
+    pub fn process_chunked(
+        &mut self,
+        samples: &[f32],
+        chunk_len: NonZeroUsize,
+        sink: &mut impl BinSink,
+        mut on_progress: impl FnMut(f32),
+    ) {
+        if samples.is_empty() {
+            on_progress(1.0);
+            return;
+        }
+        let mut processed_count = 0;
+        for chunk in samples.chunks(chunk_len.get()) {
+            self.process_into(chunk, sink);
+            processed_count += chunk.len();
+            #[allow(clippy::cast_possible_truncation)]
+            #[allow(clippy::cast_precision_loss)]
+            let progress = (processed_count as f64 / samples.len() as f64) as f32;
+            on_progress(progress);
+        }
+    }
+
+    #[must_use]
+    pub fn finish(self) -> Option<FilteredWaveformBin> {
+        self.finish_unquantized()
+            .as_ref()
+            .map(FilteredWaveformBinF32::quantize)
+    }
+
+    /// Finish the trailing bin and push it into `sink`
+    ///
+    /// Same as [`Self::finish()`], but the trailing bin is pushed into
+    /// the sink that has already received all preceding bins from
+    /// [`Self::process_into()`]. Nothing is pushed if the trailing bin
+    /// is empty.
+    pub fn finish_into(self, sink: &mut impl BinSink) {
+        let index = self.completed_bins_count;
+        if let Some(bin) = self.finish() {
+            sink.push_bin(index, &bin);
+        }
+    }
+
+    /// Same as [`Self::finish()`] but without quantizing the bin
+    #[must_use]
+    pub fn finish_unquantized(mut self) -> Option<FilteredWaveformBinF32> {
+        self.finish_bin()
+    }
+
+    /// Same as [`Self::finish()`] but also returns [`Self::max_unclamped_energy()`]
+    ///
+    /// The maximum includes the trailing bin.
+    #[must_use]
+    pub fn finish_with_max_unclamped_energy(mut self) -> (Option<FilteredWaveformBin>, f32) {
+        let bin = self.finish_bin();
+        (
+            bin.as_ref().map(FilteredWaveformBinF32::quantize),
+            self.max_unclamped_energy,
+        )
+    }
+
+    /// Same as [`Self::finish()`] but drops a sparsely filled trailing bin
+    ///
+    /// The trailing bin is only returned if it contains at least
+    /// `min_fill_ratio` times the number of samples per bin, e.g.
+    /// `0.25` requires the bin to be filled at least by a quarter.
+    /// The RMS of bins that contain only a few samples is unreliable
+    /// and could cause visual artifacts at the end of the waveform.
+    #[must_use]
+    pub fn finish_with_min_fill_ratio(self, min_fill_ratio: f32) -> Option<FilteredWaveformBin> {
+        #[allow(clippy::cast_precision_loss)]
+        let fill_ratio =
+            self.accumulator.sample_count() as f32 / self.bin_boundaries.samples_per_bin();
+        if fill_ratio < min_fill_ratio {
+            return None;
+        }
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, sync::Arc};
+
+    use crate::{compute_waveform, FilteredWaveformBin};
+
+    use biquad::{Coefficients, Hertz, Q_BUTTERWORTH_F32};
+
+    use super::{
+        butterworth_coefficients, AllBandSource, BinSink, ButterworthType, CapacityExceeded,
+        CompensatedSum, EnergyAveraging, EnergyMetric, FilteredEnergyScale, FilteredPeakHoldConfig,
+        FilteredWaveformBinAccumulator, PeakHoldDecay, ThreeBandFilterCoefficients,
+        ThreeBandFilterFreqConfig, WaveformFilter, WaveformFilterConfig, WaveformFilterConfigError,
+        DEFAULT_ENERGY_SCALE,
+    };
+
+    const fn coefficients_to_array(coefficients: Coefficients<f32>) -> [f32; 5] {
+        let Coefficients { a1, a2, b0, b1, b2 } = coefficients;
+        [a1, a2, b0, b1, b2]
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)] // Identical computations
+    fn butterworth_coefficients_from_params() {
+        for sample_rate_hz in [8_000.0, 44_100.0, 48_000.0, 96_000.0] {
+            for cutoff_hz in [20.0, 160.0, 200.0, 1_200.0, 1_600.0, 4_000.0] {
+                let fs = Hertz::<f32>::from_hz(sample_rate_hz).unwrap();
+                let f0 = Hertz::<f32>::from_hz(cutoff_hz).unwrap();
+                for (biquad_type, filter_type) in [
+                    (biquad::Type::LowPass, ButterworthType::LowPass),
+                    (biquad::Type::HighPass, ButterworthType::HighPass),
+                ] {
+                    let expected =
+                        Coefficients::<f32>::from_params(biquad_type, fs, f0, Q_BUTTERWORTH_F32)
+                            .unwrap();
+                    let actual = butterworth_coefficients(filter_type, sample_rate_hz, cutoff_hz);
+                    assert_eq!(
+                        coefficients_to_array(expected),
+                        coefficients_to_array(actual)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compensated_sum_of_long_constant_signal() {
+        const COUNT: u32 = 10_000_000;
+        let sample = 0.1f32;
+        let sample_f64 = f64::from(sample);
+        let square = sample_f64 * sample_f64;
+        let mut naive_sum = 0.0f64;
+        let mut compensated_sum = CompensatedSum::default();
+        for _ in 0..COUNT {
+            naive_sum += square;
+            compensated_sum.add(square);
+        }
+        let expected_sum = square * f64::from(COUNT);
+        let naive_error = (naive_sum - expected_sum).abs();
+        let compensated_error = (compensated_sum.sum() - expected_sum).abs();
+        assert!(compensated_error < naive_error);
+        assert!(compensated_error <= expected_sum * f64::EPSILON);
+    }
+
+    #[test]
+    fn bin_count_for_samples() {
+        let mut filter = WaveformFilter::new(Default::default());
+        for sample_count in [0, 1, 2, 293, 294, 295, 500, 1000, 1, 0, 10_000] {
+            let expected = filter.bin_count_for_samples(sample_count);
+            let mut bins = Vec::new();
+            filter.process_into(&vec![0.5; sample_count], &mut bins);
+            assert_eq!(expected, bins.len());
+        }
+    }
+
+    #[test]
+    fn process_chunked() {
+        let samples = (0..10_000)
+            .map(|i| f32::from(i16::try_from(i % 100).unwrap()) / 100.0)
+            .collect::<Vec<_>>();
+        let mut expected_bins = Vec::new();
+        WaveformFilter::default().process_into(&samples, &mut expected_bins);
+        let mut bins = Vec::new();
+        let mut progress = Vec::new();
+        WaveformFilter::default().process_chunked(
+            &samples,
+            NonZeroUsize::new(3_000).unwrap(),
+            &mut bins,
+            |fraction| progress.push(fraction),
+        );
+        assert_eq!(vec![0.3, 0.6, 0.9, 1.0], progress);
+        assert_eq!(expected_bins.len(), bins.len());
+        for (expected, actual) in expected_bins.iter().zip(&bins) {
+            assert_eq!(expected.all.peak, actual.all.peak);
+            assert_eq!(expected.all.energy, actual.all.energy);
+        }
+        // Completed without any samples
+        progress.clear();
+        WaveformFilter::default().process_chunked(
+            &[],
+            NonZeroUsize::new(3_000).unwrap(),
+            &mut bins,
+            |fraction| progress.push(fraction),
+        );
+        assert_eq!(vec![1.0], progress);
+    }
+
+    #[test]
+    fn custom_bin_boundaries() {
+        let config = WaveformFilterConfig::DEFAULT;
+        let samples_per_bin = config.samples_per_bin();
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        let samples_per_bin = samples_per_bin as usize;
+        let samples = (0..10_000)
+            .map(|i| f32::from(i16::try_from(i % 100).unwrap()) / 100.0)
+            .collect::<Vec<_>>();
+        let expected_bins = compute_waveform(config.clone(), &samples);
+        let mut accumulator =
+            FilteredWaveformBinAccumulator::new(config.sample_rate_hz, config.filter_freqs);
+        let mut bins = Vec::new();
+        for chunk in samples.chunks(samples_per_bin) {
+            for &sample in chunk {
+                accumulator.add_sample(sample);
+            }
+            bins.extend(accumulator.finish_bin());
+        }
+        assert!(accumulator.finish_bin().is_none());
+        assert_eq!(expected_bins.len(), bins.len());
+        for (expected, actual) in expected_bins.iter().zip(&bins) {
+            assert_eq!(expected.all.energy, actual.all.energy);
+            assert_eq!(expected.low.energy, actual.low.energy);
+            assert_eq!(expected.high.peak, actual.high.peak);
+        }
+    }
+
+    #[test]
+    fn process_into_slice() {
+        let samples = vec![0.5; 1000];
+        let mut filter = WaveformFilter::default();
+        let mut out: [FilteredWaveformBin; 3] = Default::default();
+        assert_eq!(
+            Err(CapacityExceeded {
+                required: 4,
+                capacity: 3
+            }),
+            filter.process_into_slice(&vec![0.5; 1200], &mut out)
+        );
+        assert_eq!(Ok(3), filter.process_into_slice(&samples, &mut out));
+        assert!(out.iter().all(|bin| !bin.is_silent()));
+        assert_eq!(Ok(0), filter.process_into_slice(&[], &mut out));
+    }
+
+    #[test]
+    fn peak_hold_decays_per_band() {
+        let mut samples = vec![0.0; 10_000];
+        samples[0] = 1.0;
+        let config = WaveformFilterConfig {
+            peak_hold: FilteredPeakHoldConfig {
+                all: PeakHoldDecay::Linear { per_second: 10.0 },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let bins = compute_waveform(config, &samples);
+        assert!(bins[0].all.peak > bins[1].all.peak);
+        assert!(bins[1].all.peak > bins[2].all.peak);
+        assert!(!bins[2].all.peak.is_zero());
+        assert!(bins.last().unwrap().all.peak.is_zero());
+        // No peak hold for the high band.
+        assert!(bins[1].high.peak.is_zero());
+    }
+
+    #[test]
+    fn peak_hold_decays_in_db() {
+        let mut samples = vec![0.0; 10_000];
+        samples[0] = 1.0;
+        let config = WaveformFilterConfig {
+            bins_per_sec: 10.0,
+            peak_hold: FilteredPeakHoldConfig {
+                all: PeakHoldDecay::Db { per_second: 20.0 },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut filter = WaveformFilter::new(config);
+        let bins = samples
+            .iter()
+            .filter_map(|&sample| filter.add_sample_unquantized(sample))
+            .collect::<Vec<_>>();
+        assert!((bins[0].all.peak - 1.0).abs() < 1e-6);
+        // -2 dB per bin
+        for pair in bins.windows(2) {
+            let db = 20.0 * (pair[1].all.peak / pair[0].all.peak).log10();
+            assert!((db + 2.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn energy_metric_mean_square() {
+        let samples = (0..10_000)
+            .map(|i| if i % 20 < 10 { 0.5 } else { -0.5 })
+            .collect::<Vec<_>>();
+        let rms_bins = compute_waveform(Default::default(), &samples);
+        let config = WaveformFilterConfig {
+            energy_metric: EnergyMetric::MeanSquare,
+            ..Default::default()
+        };
+        let bins = compute_waveform(config, &samples);
+        assert_eq!(rms_bins.len(), bins.len());
+        for (rms_bin, bin) in rms_bins.iter().zip(&bins) {
+            assert_eq!(rms_bin.all.peak, bin.all.peak);
+            let rms = rms_bin.all.energy.to_f32();
+            assert!((rms * rms - bin.all.energy.to_f32()).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn energy_scale_per_band() {
+        let samples = (0..10_000)
+            .map(|i| if i % 20 < 10 { 0.25 } else { -0.25 })
+            .collect::<Vec<_>>();
+        let default_bins = compute_waveform(Default::default(), &samples);
+        let config = WaveformFilterConfig {
+            energy_scale: FilteredEnergyScale {
+                all: 2.0 * std::f32::consts::SQRT_2,
+                low: 0.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let bins = compute_waveform(config, &samples);
+        assert_eq!(default_bins.len(), bins.len());
+        for (default_bin, bin) in default_bins.iter().zip(&bins) {
+            let default_energy = i32::from(default_bin.all.energy.0);
+            assert!((2 * default_energy - i32::from(bin.all.energy.0)).abs() <= 1);
+            assert!(bin.low.energy.is_zero());
+            assert_eq!(default_bin.mid.energy, bin.mid.energy);
+            assert_eq!(default_bin.high.energy, bin.high.energy);
+            assert_eq!(default_bin.all.peak, bin.all.peak);
+        }
+    }
+
+    #[test]
+    fn set_bins_per_sec() {
+        let samples = vec![0.5; 44_100];
+        let mut filter = WaveformFilter::new(WaveformFilterConfig {
+            sample_rate_hz: 44_100.0,
+            bins_per_sec: 100.0,
+            ..Default::default()
+        });
+        let mut bins = Vec::new();
+        filter.process_into(&samples[..22_050], &mut bins);
+        // 22_050 = 50 * 441
+        assert_eq!(49, bins.len());
+        bins.extend(filter.set_bins_per_sec(10.0).unwrap());
+        assert_eq!(50, bins.len());
+        assert!(filter.set_bins_per_sec(10.0).unwrap().is_none());
+        for bins_per_sec in [0.0, -10.0, f32::INFINITY, f32::NAN] {
+            assert!(matches!(
+                filter.set_bins_per_sec(bins_per_sec),
+                Err(WaveformFilterConfigError::InvalidBinsPerSec { .. })
+            ));
+        }
+        filter.process_into(&samples[22_050..], &mut bins);
+        bins.extend(filter.finish());
+        assert_eq!(55, bins.len());
+    }
+
+    #[derive(Default)]
+    struct BinIndices(Vec<usize>);
+
+    impl BinSink for BinIndices {
+        fn push_bin(&mut self, index: usize, _bin: &FilteredWaveformBin) {
+            self.0.push(index);
+        }
+    }
+
+    #[test]
+    fn finish_into() {
+        let config = WaveformFilterConfig::DEFAULT;
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        let samples_per_bin = config.samples_per_bin() as usize;
+        let mut filter = WaveformFilter::new(config.clone());
+        let mut indices = BinIndices::default();
+        filter.process_into(&vec![0.5; samples_per_bin * 2 + 1], &mut indices);
+        filter.finish_into(&mut indices);
+        assert_eq!(vec![0, 1, 2], indices.0);
+        // Empty trailing bin
+        let filter = WaveformFilter::new(config);
+        let mut indices = BinIndices::default();
+        filter.finish_into(&mut indices);
+        assert!(indices.0.is_empty());
+    }
+
+    #[test]
+    fn default_configs() {
+        let mut config = WaveformFilterConfig::DEFAULT;
+        assert!(config.is_default());
+        assert!(config.filter_freqs.is_default());
+        config.filter_freqs = ThreeBandFilterFreqConfig::rekordbox();
+        assert!(!config.is_default());
+        assert!(!config.filter_freqs.is_default());
+        config.filter_freqs.reset_to_default();
+        assert!(config.is_default());
+        config.bins_per_sec += 1.0;
+        assert!(!config.is_default());
+        config.reset_to_default();
+        assert!(config.is_default());
+    }
+
+    #[test]
+    fn shared_coefficients() {
+        let samples = (0..4410)
+            .map(|i| if i % 20 < 10 { 0.5 } else { -0.5 })
+            .collect::<Vec<_>>();
+        let config = WaveformFilterConfig::default();
+        let coefficients = Arc::new(ThreeBandFilterCoefficients::new(
+            config.sample_rate_hz,
+            config.filter_freqs.clone(),
+        ));
+        let mut expected = Vec::new();
+        let mut filter = WaveformFilter::new(config.clone());
+        filter.process_into(&samples, &mut expected);
+        for _ in 0..2 {
+            let mut filter =
+                WaveformFilter::with_coefficients(config.clone(), Arc::clone(&coefficients));
+            assert_eq!(
+                WaveformFilter::new(config.clone()).filter_coefficients(),
+                filter.filter_coefficients()
+            );
+            let mut bins = Vec::new();
+            filter.process_into(&samples, &mut bins);
+            assert_eq!(expected.len(), bins.len());
+            for (expected, actual) in expected.iter().zip(&bins) {
+                assert_eq!(expected.low.energy, actual.low.energy);
+                assert_eq!(expected.mid.energy, actual.mid.energy);
+                assert_eq!(expected.high.energy, actual.high.energy);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "matching coefficients")]
+    fn mismatching_coefficients() {
+        let coefficients = Arc::new(ThreeBandFilterCoefficients::new(
+            48_000.0,
+            ThreeBandFilterFreqConfig::DEFAULT,
+        ));
+        drop(WaveformFilter::with_coefficients(
+            WaveformFilterConfig::default(),
+            coefficients,
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "valid mid band width")]
+    fn narrow_mid_band() {
+        drop(WaveformFilter::new(WaveformFilterConfig {
+            filter_freqs: ThreeBandFilterFreqConfig {
+                low_lp_hz: 1000.0,
+                low_hp_hz: 990.0,
+                high_lp_hz: 1010.0,
+                high_hp_hz: 1005.0,
+            },
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn add_samples_count() {
+        let samples = vec![0.5; 10_000];
+        let mut filter = WaveformFilter::default();
+        let expected_count = filter.bin_count_for_samples(samples.len());
+        let mut bins = Vec::new();
+        assert_eq!(
+            expected_count,
+            filter.add_samples_count(&samples, |bin| bins.push(bin))
+        );
+        assert_eq!(expected_count, bins.len());
+        assert_eq!(0, filter.add_samples_count(&[], |_| unreachable!()));
+    }
+
+    #[test]
+    fn all_band_from_sum_of_bands() {
+        let samples = (0..10_000)
+            .map(|i| if i % 40 < 20 { 0.5 } else { -0.5 })
+            .collect::<Vec<_>>();
+        let mut config = WaveformFilterConfig::DEFAULT;
+        config.all_source = AllBandSource::SumOfBands;
+        let wideband_bins = compute_waveform(Default::default(), &samples);
+        let bins = compute_waveform(config, &samples);
+        assert_eq!(wideband_bins.len(), bins.len());
+        for (wideband_bin, bin) in wideband_bins.iter().zip(&bins) {
+            assert_eq!(wideband_bin.low.energy, bin.low.energy);
+            assert_eq!(wideband_bin.mid.energy, bin.mid.energy);
+            assert_eq!(wideband_bin.high.energy, bin.high.energy);
+        }
+        assert!(wideband_bins
+            .iter()
+            .zip(&bins)
+            .any(|(wideband_bin, bin)| wideband_bin.all.energy != bin.all.energy));
+    }
+
+    #[test]
+    fn discard_warmup_bins() {
+        let samples = vec![0.5; 10_000];
+        let bins = compute_waveform(Default::default(), &samples);
+        let mut config = WaveformFilterConfig::DEFAULT;
+        config.discard_warmup_bins = 3;
+        let mut filter = WaveformFilter::new(config.clone());
+        assert_eq!(
+            bins.len() - 1 - 3,
+            filter.bin_count_for_samples(samples.len())
+        );
+        let warm_bins = compute_waveform(config.clone(), &samples);
+        assert_eq!(bins.len() - 3, warm_bins.len());
+        for (bin, warm_bin) in bins[3..].iter().zip(&warm_bins) {
+            assert_eq!(bin.low.energy, warm_bin.low.energy);
+        }
+        let mut out = vec![FilteredWaveformBin::default(); warm_bins.len()];
+        assert_eq!(
+            warm_bins.len() - 1,
+            filter.process_into_slice(&samples, &mut out).unwrap()
+        );
+        config.discard_warmup_bins = bins.len();
+        assert!(compute_waveform(config, &samples).is_empty());
+    }
+
+    #[test]
+    fn discard_warmup_bins_with_peak_hold() {
+        // Loud peaks only within the discarded bins
+        let samples = (0..10_000)
+            .map(|i| if i < 500 { 1.0 } else { 0.25 })
+            .collect::<Vec<_>>();
+        let mut config = WaveformFilterConfig::DEFAULT;
+        config.peak_hold.all = PeakHoldDecay::Linear { per_second: 0.1 };
+        let held_bins = compute_waveform(config.clone(), &samples);
+        assert!(held_bins[3].all.peak.to_f32() > 0.9);
+        config.discard_warmup_bins = 3;
+        let warm_bins = compute_waveform(config, &samples);
+        assert_eq!(held_bins.len() - 3, warm_bins.len());
+        assert!(warm_bins[0].all.peak.to_f32() < 0.3);
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    #[allow(clippy::float_cmp)]
+    fn exponential_energy_averaging_step_response() {
+        const SAMPLES_PER_BIN: usize = 294;
+        const TIME_CONSTANT_SECS: f32 = 0.02;
+        // Silence followed by a constant signal
+        let samples = (0..70 * SAMPLES_PER_BIN)
+            .map(|i| if i < 10 * SAMPLES_PER_BIN { 0.0 } else { 0.5 })
+            .collect::<Vec<_>>();
+        let config = WaveformFilterConfig::DEFAULT;
+        assert_eq!(SAMPLES_PER_BIN as f32, config.samples_per_bin());
+        let block_bins = compute_waveform(config.clone(), &samples);
+        let ema_bins = compute_waveform(
+            WaveformFilterConfig {
+                energy_averaging: EnergyAveraging::Exponential {
+                    time_constant_secs: TIME_CONSTANT_SECS,
+                },
+                ..config.clone()
+            },
+            &samples,
+        );
+        assert_eq!(block_bins.len(), ema_bins.len());
+        for (block_bin, ema_bin) in block_bins[..10].iter().zip(&ema_bins) {
+            assert!(block_bin.all.energy.is_zero());
+            assert!(ema_bin.all.energy.is_zero());
+        }
+        let block_energy = block_bins[10].all.energy.to_f32();
+        assert!(block_energy > 0.7);
+        for (index, ema_bin) in ema_bins[10..].iter().enumerate() {
+            // The mean square rises exponentially towards the block mean square
+            let secs = ((index + 1) * SAMPLES_PER_BIN) as f32 / config.sample_rate_hz;
+            let expected = block_energy * (1.0 - (-secs / TIME_CONSTANT_SECS).exp()).sqrt();
+            assert!((ema_bin.all.energy.to_f32() - expected).abs() <= 2.0 / 255.0);
+        }
+        // Lagging behind within the first time constants
+        assert!(ema_bins[10].all.energy < block_bins[10].all.energy);
+        assert!(ema_bins[12].all.energy < block_bins[12].all.energy);
+        // Converged after 10 time constants, i.e. 30 bins
+        for (block_bin, ema_bin) in block_bins[40..].iter().zip(&ema_bins[40..]) {
+            assert_eq!(block_bin.all.energy, ema_bin.all.energy);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "supported energy averaging")]
+    fn unsupported_energy_averaging() {
+        drop(WaveformFilter::new(WaveformFilterConfig {
+            energy_metric: EnergyMetric::Variance,
+            energy_averaging: EnergyAveraging::Exponential {
+                time_constant_secs: 0.1,
+            },
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn energy_metric_mean_abs_and_variance() {
+        // Square wave with a DC offset of 0.25
+        let samples = (0..10_000)
+            .map(|i| if i % 20 < 10 { 0.75 } else { -0.25 })
+            .collect::<Vec<_>>();
+        let energies = |energy_metric| {
+            let config = WaveformFilterConfig {
+                energy_metric,
+                ..Default::default()
+            };
+            compute_waveform(config, &samples)
+                .iter()
+                .map(|bin| bin.all.energy.to_f32())
+                .collect::<Vec<_>>()
+        };
+        let scale = DEFAULT_ENERGY_SCALE;
+        for energy in &energies(EnergyMetric::MeanAbs)[1..10] {
+            assert!((energy - 0.5 * scale).abs() < 0.01);
+        }
+        for energy in &energies(EnergyMetric::Variance)[1..10] {
+            assert!((energy - 0.25 * scale * scale).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)] // Exact for no samples
+    fn elapsed_secs() {
+        let mut filter = WaveformFilter::new(WaveformFilterConfig {
+            sample_rate_hz: 48_000.0,
+            ..Default::default()
+        });
+        assert_eq!(0.0, filter.elapsed_secs());
+        // Warming up the filters does not advance the time
+        filter.warm_up(&vec![0.5; 4_800]);
+        assert_eq!(0, filter.added_samples_count());
+        assert_eq!(0.0, filter.elapsed_secs());
+        for _ in 0..24_000 {
+            filter.add_sample(0.5);
+        }
+        assert_eq!(24_000, filter.added_samples_count());
+        assert!((filter.elapsed_secs() - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn add_samples_with_gain() {
+        let samples = (0..10_000)
+            .map(|i| if i % 20 < 10 { 0.5 } else { -0.5 })
+            .collect::<Vec<_>>();
+        // Fade out after the first half
+        let gain = |index| if index < 5_000 { 1.0 } else { 0.0 };
+        let mut filter = WaveformFilter::default();
+        let mut bins = Vec::new();
+        for chunk in samples.chunks(999) {
+            filter.add_samples_with_gain(chunk, gain, &mut bins);
+        }
+        assert_eq!(10_000, filter.added_samples_count());
+        assert!((filter.elapsed_secs() - 10_000.0 / 44_100.0).abs() < 1e-6);
+        let mut expected_filter = WaveformFilter::default();
+        let mut expected_bins = Vec::new();
+        let faded_samples = samples
+            .iter()
+            .enumerate()
+            .map(|(index, sample)| sample * gain(index as u64))
+            .collect::<Vec<_>>();
+        expected_filter.process_into(&faded_samples, &mut expected_bins);
+        assert_eq!(expected_bins.len(), bins.len());
+        for (expected, actual) in expected_bins.iter().zip(&bins) {
+            assert_eq!(expected.all.peak, actual.all.peak);
+            assert_eq!(expected.all.energy, actual.all.energy);
+        }
+        assert!(!bins[5].all.energy.is_zero());
+        assert!(bins.last().unwrap().all.peak.is_zero());
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn fractional_samples_per_bin_without_drift() {
+        let config = WaveformFilterConfig {
+            sample_rate_hz: 8_000.0,
+            bins_per_sec: 101.0,
+            ..Default::default()
+        };
+        let ideal_samples_per_bin = f64::from(config.samples_per_bin());
+        assert!(ideal_samples_per_bin.fract() > 0.0);
+        let mut filter = WaveformFilter::new(config);
+        let mut bin_ends = Vec::new();
+        let mut sample_index = 0u64;
+        while bin_ends.len() < 200_000 {
+            if filter.add_sample(0.0).is_some() {
+                // The bin has been finished before adding this sample
+                bin_ends.push(sample_index);
+            }
+            sample_index += 1;
+        }
+        let mut bin_start = 0;
+        for (bin_index, &bin_end) in bin_ends.iter().enumerate() {
+            let ideal_bin_end = (bin_index + 1) as f64 * ideal_samples_per_bin;
+            assert!((bin_end as f64 - ideal_bin_end).abs() <= 1.0);
+            let sample_count = (bin_end - bin_start) as f64;
+            assert!((sample_count - ideal_samples_per_bin).abs() <= 1.0);
+            bin_start = bin_end;
+        }
+    }
+
+    #[test]
+    fn energy_reference_level() {
+        // Square waves with an RMS of -12 dBFS and -18 dBFS
+        let square_wave = |amplitude: f32| {
+            (0..10_000)
+                .map(|i| if i % 20 < 10 { amplitude } else { -amplitude })
+                .collect::<Vec<_>>()
+        };
+        let config = WaveformFilterConfig {
+            energy_reference_db: Some(-12.0),
+            ..Default::default()
+        };
+        let reference_amplitude = 10.0f32.powf(-12.0 / 20.0);
+        let bins = compute_waveform(config.clone(), &square_wave(reference_amplitude));
+        for bin in &bins[1..bins.len() - 1] {
+            assert!((bin.all.energy.to_f32() - 0.5).abs() < 0.01);
+        }
+        let bins = compute_waveform(config.clone(), &square_wave(reference_amplitude / 2.0));
+        for bin in &bins[1..bins.len() - 1] {
+            assert!((bin.all.energy.to_f32() - 0.25).abs() < 0.01);
+        }
+        // The reference level is applied before squaring the scaled RMS
+        let config = config.with_energy_metric(EnergyMetric::MeanSquare);
+        let bins = compute_waveform(config, &square_wave(reference_amplitude));
+        for bin in &bins[1..bins.len() - 1] {
+            assert!((bin.all.energy.to_f32() - 0.25).abs() < 0.01);
+        }
+        assert_eq!(
+            FilteredEnergyScale::DEFAULT,
+            WaveformFilterConfig::DEFAULT.effective_energy_scale()
+        );
+    }
+
+    fn invalid_config(edit: fn(&mut WaveformFilterConfig)) -> WaveformFilterConfigError {
+        let mut config = WaveformFilterConfig::DEFAULT;
+        edit(&mut config);
+        config.validate().unwrap_err()
+    }
+
+    #[test]
+    fn validate_config() {
+        assert_eq!(Ok(()), WaveformFilterConfig::DEFAULT.validate());
+        assert_eq!(
+            WaveformFilterConfigError::InvalidSampleRate {
+                sample_rate_hz: 0.0
+            },
+            invalid_config(|config| config.sample_rate_hz = 0.0)
+        );
+        assert_eq!(
+            WaveformFilterConfigError::InvalidSampleRate {
+                sample_rate_hz: f32::INFINITY
+            },
+            invalid_config(|config| config.sample_rate_hz = f32::INFINITY)
+        );
+        assert!(matches!(
+            invalid_config(|config| config.bins_per_sec = f32::NAN),
+            WaveformFilterConfigError::InvalidBinsPerSec { .. }
+        ));
+        assert_eq!(
+            WaveformFilterConfigError::InvalidBinsPerSec {
+                bins_per_sec: f32::INFINITY
+            },
+            invalid_config(|config| config.bins_per_sec = f32::INFINITY)
+        );
+        assert_eq!(
+            WaveformFilterConfigError::FrequencyOutOfRange { freq_hz: 10.0 },
+            invalid_config(|config| config.filter_freqs.low_hp_hz = 10.0)
+        );
+        assert_eq!(
+            WaveformFilterConfigError::InvalidCrossoverOrder,
+            invalid_config(|config| config.filter_freqs.low_hp_hz = 250.0)
+        );
+        assert_eq!(
+            WaveformFilterConfigError::AboveNyquist {
+                freq_hz: 1600.0,
+                nyquist_hz: 1000.0
+            },
+            invalid_config(|config| config.sample_rate_hz = 2000.0)
+        );
+        assert!(matches!(
+            invalid_config(|config| config.filter_freqs = ThreeBandFilterFreqConfig {
+                low_lp_hz: 200.0,
+                low_hp_hz: 160.0,
+                high_lp_hz: 220.0,
+                high_hp_hz: 210.0
+            }),
+            WaveformFilterConfigError::MidBandTooNarrow { .. }
+        ));
+        for energy_metric in [EnergyMetric::MeanAbs, EnergyMetric::Variance] {
+            let config = WaveformFilterConfig {
+                energy_metric,
+                ..Default::default()
+            };
+            assert_eq!(Ok(()), config.validate());
+            assert_eq!(
+                Err(WaveformFilterConfigError::UnsupportedEnergyAveraging { energy_metric }),
+                WaveformFilterConfig {
+                    energy_averaging: EnergyAveraging::Exponential {
+                        time_constant_secs: 0.1
+                    },
+                    ..config
+                }
+                .validate()
+            );
+        }
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)] // Identical computations
+    fn filter_coefficients_in_stage_order() {
+        let sample_rate_hz = 48_000.0;
+        let filter_freqs = ThreeBandFilterFreqConfig::rekordbox();
+        let filter = WaveformFilter::new(WaveformFilterConfig {
+            sample_rate_hz,
+            filter_freqs: filter_freqs.clone(),
+            ..Default::default()
+        });
+        let ThreeBandFilterFreqConfig {
+            low_lp_hz,
+            low_hp_hz,
+            high_lp_hz,
+            high_hp_hz,
+        } = filter_freqs;
+        let expected = [
+            (ButterworthType::LowPass, low_lp_hz),
+            (ButterworthType::LowPass, low_lp_hz),
+            (ButterworthType::HighPass, low_hp_hz),
+            (ButterworthType::LowPass, high_lp_hz),
+            (ButterworthType::HighPass, high_hp_hz),
+            (ButterworthType::HighPass, high_hp_hz),
+        ]
+        .map(|(filter_type, cutoff_hz)| {
+            coefficients_to_array(butterworth_coefficients(
+                filter_type,
+                sample_rate_hz,
+                cutoff_hz,
+            ))
+        });
+        assert_eq!(expected, filter.filter_coefficients());
+    }
+
+    #[test]
+    fn finish_with_min_fill_ratio() {
+        // 100 samples per bin
+        let config = WaveformFilterConfig {
+            sample_rate_hz: 10_000.0,
+            bins_per_sec: 100.0,
+            ..Default::default()
+        };
+        let finish = |trailing_samples_count: usize| {
+            let mut filter = WaveformFilter::new(config.clone());
+            let mut bins = Vec::new();
+            filter.process_into(&vec![0.5; 200 + trailing_samples_count], &mut bins);
+            assert_eq!(2, bins.len());
+            filter.finish_with_min_fill_ratio(0.25)
+        };
+        assert!(finish(24).is_none());
+        assert!(finish(25).is_some());
+        assert!(finish(26).is_some());
+        // Without a threshold
+        let mut filter = WaveformFilter::new(config.clone());
+        filter.process_into(&[0.5], &mut Vec::new());
+        assert!(filter.finish_with_min_fill_ratio(0.0).is_some());
+    }
+
+    #[test]
+    fn retune_config() {
+        let preset = WaveformFilterConfig {
+            bins_per_sec: 100.0,
+            filter_freqs: ThreeBandFilterFreqConfig::rekordbox(),
+            ..Default::default()
+        };
+        let config = preset.retuned_for(96_000.0).unwrap();
+        assert_eq!(
+            WaveformFilterConfig {
+                sample_rate_hz: 96_000.0,
+                ..preset.clone()
+            },
+            config
+        );
+        assert!(config.samples_per_bin() > preset.samples_per_bin());
+        // The crossovers no longer fit below the Nyquist frequency
+        assert_eq!(
+            Err(WaveformFilterConfigError::AboveNyquist {
+                freq_hz: 2000.0,
+                nyquist_hz: 1500.0
+            }),
+            preset.retuned_for(3000.0)
+        );
+        assert!(matches!(
+            preset.retuned_for(0.0),
+            Err(WaveformFilterConfigError::InvalidSampleRate { .. })
+        ));
+    }
+
+    #[test]
+    fn validate_config_energy_and_peak_hold() {
+        for time_constant_secs in [0.0, -0.1, f32::NAN, f32::INFINITY] {
+            let config = WaveformFilterConfig {
+                energy_averaging: EnergyAveraging::Exponential { time_constant_secs },
+                ..Default::default()
+            };
+            assert!(matches!(
+                config.validate(),
+                Err(WaveformFilterConfigError::InvalidEnergyTimeConstant { .. })
+            ));
+        }
+        assert_eq!(
+            WaveformFilterConfigError::InvalidPeakHoldDecay { per_second: -1.0 },
+            invalid_config(|config| config.peak_hold.mid = PeakHoldDecay::Db { per_second: -1.0 })
+        );
+        assert!(matches!(
+            invalid_config(|config| config.peak_hold.all = PeakHoldDecay::Linear {
+                per_second: f32::NAN
+            }),
+            WaveformFilterConfigError::InvalidPeakHoldDecay { .. }
+        ));
+        assert_eq!(
+            WaveformFilterConfigError::InvalidEnergyScale { energy_scale: -1.0 },
+            invalid_config(|config| config.energy_scale.high = -1.0)
+        );
+        assert!(matches!(
+            invalid_config(|config| config.energy_scale.low = f32::NAN),
+            WaveformFilterConfigError::InvalidEnergyScale { .. }
+        ));
+        for energy_reference_db in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY, -1000.0] {
+            let config = WaveformFilterConfig {
+                energy_reference_db: Some(energy_reference_db),
+                ..Default::default()
+            };
+            assert!(matches!(
+                config.validate(),
+                Err(WaveformFilterConfigError::InvalidEnergyReference { .. })
+            ));
+        }
+        let config = WaveformFilterConfig {
+            energy_reference_db: Some(-20.0),
+            ..Default::default()
+        };
+        assert_eq!(Ok(()), config.validate());
     }
 }
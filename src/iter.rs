@@ -0,0 +1,168 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{FilteredWaveformBin, WaveformFilter};
+
+/// Iterator over the bins of a stream of samples
+///
+/// Yields completed bins as soon as they become available and
+/// finally the trailing bin when the samples are exhausted.
+///
+/// Created by [`WaveformFilter::into_bins()`].
+#[derive(Debug)]
+pub struct WaveformFilterBins<I> {
+    filter: Option<WaveformFilter>,
+    samples: I,
+}
+
+impl<I> Iterator for WaveformFilterBins<I>
+where
+    I: Iterator<Item = f32>,
+{
+    type Item = FilteredWaveformBin;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let filter = self.filter.as_mut()?;
+        for sample in self.samples.by_ref() {
+            if let Some(bin) = filter.add_sample(sample) {
+                return Some(bin);
+            }
+        }
+        self.filter.take()?.finish()
+    }
+}
+
+/// Iterator over the bins of a stream of samples with timestamps
+///
+/// Yields the center time of each bin in seconds together with the bin.
+/// The times are calculated from the actual number of samples in
+/// each bin, i.e. the center of the shorter, trailing bin is located
+/// at the center of its actual span.
+///
+/// Created by [`WaveformFilter::into_timed_bins()`].
+#[derive(Debug)]
+pub struct WaveformFilterTimedBins<I> {
+    filter: Option<WaveformFilter>,
+    samples: I,
+    sample_rate_hz: f32,
+    sample_count: usize,
+    bin_start: usize,
+}
+
+impl<I> WaveformFilterTimedBins<I> {
+    #[allow(clippy::cast_precision_loss)]
+    fn center_secs(&self, bin_start: usize, bin_end: usize) -> f32 {
+        let center = (bin_start + bin_end) as f64 / 2.0;
+        #[allow(clippy::cast_possible_truncation)]
+        let center_secs = (center / f64::from(self.sample_rate_hz)) as f32;
+        center_secs
+    }
+}
+
+impl<I> Iterator for WaveformFilterTimedBins<I>
+where
+    I: Iterator<Item = f32>,
+{
+    type Item = (f32, FilteredWaveformBin);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let filter = self.filter.as_mut()?;
+        for sample in self.samples.by_ref() {
+            let bin_start = self.bin_start;
+            let bin_end = self.sample_count;
+            if filter.is_bin_complete() {
+                // Also for discarded bins
+                self.bin_start = bin_end;
+            }
+            self.sample_count += 1;
+            if let Some(bin) = filter.add_sample(sample) {
+                return Some((self.center_secs(bin_start, bin_end), bin));
+            }
+        }
+        let bin = self.filter.take()?.finish()?;
+        Some((self.center_secs(self.bin_start, self.sample_count), bin))
+    }
+}
+
+impl WaveformFilter {
+    /// Same as [`Self::into_bins()`], but with timestamps
+    #[must_use]
+    pub fn into_timed_bins<I>(self, samples: I) -> WaveformFilterTimedBins<I::IntoIter>
+    where
+        I: IntoIterator<Item = f32>,
+    {
+        WaveformFilterTimedBins {
+            sample_rate_hz: self.sample_rate_hz(),
+            filter: Some(self),
+            samples: samples.into_iter(),
+            sample_count: 0,
+            bin_start: 0,
+        }
+    }
+
+    /// Consume a stream of samples and iterate over the resulting bins
+    ///
+    /// The samples may arrive in chunks, e.g. from a decoder, that could
+    /// be flattened into a single stream of samples. The trailing bin is
+    /// flushed when the samples are exhausted.
+    #[must_use]
+    pub fn into_bins<I>(self, samples: I) -> WaveformFilterBins<I::IntoIter>
+    where
+        I: IntoIterator<Item = f32>,
+    {
+        WaveformFilterBins {
+            filter: Some(self),
+            samples: samples.into_iter(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compute_waveform, WaveformFilter, WaveformFilterConfig};
+
+    #[test]
+    fn into_bins_from_chunks() {
+        let chunks = (0..10)
+            .map(|i| vec![f32::from(u8::try_from(i).unwrap()) / 10.0; 1000])
+            .collect::<Vec<_>>();
+        let expected_bins = compute_waveform(Default::default(), &chunks.concat());
+        let bins = WaveformFilter::default()
+            .into_bins(chunks.into_iter().flatten())
+            .collect::<Vec<_>>();
+        assert_eq!(expected_bins.len(), bins.len());
+        for (expected, actual) in expected_bins.iter().zip(&bins) {
+            assert_eq!(expected.all.peak, actual.all.peak);
+            assert_eq!(expected.all.energy, actual.all.energy);
+        }
+    }
+
+    #[test]
+    fn timed_bins() {
+        let config = WaveformFilterConfig {
+            sample_rate_hz: 8000.0,
+            bins_per_sec: 40.0,
+            ..Default::default()
+        };
+        let times = WaveformFilter::new(config.clone())
+            .into_timed_bins(vec![0.5; 500])
+            .map(|(secs, _)| secs)
+            .collect::<Vec<_>>();
+        assert_eq!(3, times.len());
+        assert!((times[0] - 0.0125).abs() < 1e-6);
+        assert!((times[1] - 0.0375).abs() < 1e-6);
+        // Shorter trailing bin
+        assert!((times[2] - 0.056_25).abs() < 1e-6);
+        // Discarded bins are skipped
+        let config = WaveformFilterConfig {
+            discard_warmup_bins: 1,
+            ..config
+        };
+        let times = WaveformFilter::new(config)
+            .into_timed_bins(vec![0.5; 500])
+            .map(|(secs, _)| secs)
+            .collect::<Vec<_>>();
+        assert_eq!(2, times.len());
+        assert!((times[0] - 0.0375).abs() < 1e-6);
+    }
+}
@@ -7,8 +7,84 @@
 #![allow(clippy::similar_names)]
 #![doc = include_str!("../README.md")]
 
+mod activity;
+pub use activity::{ActivityDetector, ActivityDetectorConfig};
+
+mod analysis;
+pub use analysis::{
+    active_range, bin_slice, clipping_ratio, clipping_ratio_above, dominant_band_distribution,
+    dominant_band_overall, dominant_band_runs, join_with_crossfade, loudness_range_db,
+    onset_envelope_timings_ms, peak_energy_position_secs, peak_energy_position_secs_smoothed,
+    segment_bins, suggest_display_gain, track_crest_factor, track_spectral_centroid,
+    waveform_similarity, LOUDNESS_RANGE_SILENCE_THRESHOLD_DB,
+};
+
+mod cache;
+pub use cache::WaveformCache;
+
+mod color;
+pub use color::{lab_to_linear_rgb, linear_rgb_to_lab, LinearRgb, SrgbRgb};
+
+mod compute;
+pub use compute::{
+    compute_waveform, compute_waveform_bounded, compute_waveform_cancellable,
+    compute_waveform_phase_averaged, compute_waveform_range,
+    compute_waveform_with_max_unclamped_energy, compute_waveform_with_overview,
+};
+
+mod dc_offset;
+pub use dc_offset::{DcOffsetWaveformFilter, FilteredWaveformBinWithDcOffset};
+
+mod file;
+pub use file::{read_waveform_file, write_waveform_file};
+
 mod filter;
-pub use filter::{ThreeBandFilterFreqConfig, WaveformFilter, WaveformFilterConfig};
+pub use filter::{
+    AllBandSource, BinSink, CapacityExceeded, EnergyAveraging, EnergyMetric, FilteredEnergyScale,
+    FilteredPeakHoldConfig, FilteredWaveformBinAccumulator, PeakHoldDecay,
+    ThreeBandFilterCoefficients, ThreeBandFilterFreqConfig, WaveformBinAccumulator, WaveformFilter,
+    WaveformFilterConfig, WaveformFilterConfigError,
+};
+
+mod iter;
+pub use iter::{WaveformFilterBins, WaveformFilterTimedBins};
+
+mod metric;
+pub use metric::{extract_metric, selector, transpose_band_energies};
+
+mod pitch;
+pub use pitch::{FilteredWaveformBinWithPitch, PitchWaveformFilter};
+
+mod punch;
+pub use punch::{PunchTracker, PunchTrackerConfig};
+
+mod render;
+pub use render::{
+    bins_to_sparkline, color_at, render_to_rgba, render_waveform_rgba, WaveformFill,
+    WaveformHeight, WaveformLayout, WaveformStyle,
+};
+
+mod resample;
+
+mod smoothing;
+pub use smoothing::ColorSmoother;
+
+mod spectrum;
+pub use spectrum::from_spectrum;
+
+#[cfg(test)]
+mod test_util;
 
 mod waveform;
-pub use waveform::{FilteredWaveformBin, FilteredWaveformVal, WaveformBin, WaveformVal};
+pub use waveform::{
+    Band, FilteredWaveformBin, FilteredWaveformBinF32, FilteredWaveformVal, SpectralColorWeights,
+    WaveformBin, WaveformBinF32, WaveformVal,
+};
+
+#[cfg(feature = "cached")]
+mod store;
+#[cfg(feature = "cached")]
+pub use store::{WaveformStorage, WaveformStore};
+
+mod window;
+pub use window::{Window, WindowedWaveformFilter};
@@ -0,0 +1,151 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Extraction of a single metric from bins
+
+use crate::FilteredWaveformBin;
+
+/// Extract a single metric from each bin
+///
+/// Intended for feeding the bins into plotting libraries or machine
+/// learning models that expect a flat array. See [`selector`] for
+/// common choices of the metric.
+#[must_use]
+pub fn extract_metric(
+    bins: &[FilteredWaveformBin],
+    selector: impl Fn(&FilteredWaveformBin) -> f32,
+) -> Vec<f32> {
+    bins.iter().map(selector).collect()
+}
+
+/// Extract the energies of all bands from each bin
+///
+/// Returns one series per band in the order all, low, mid, and high,
+/// e.g. for stacked area charts.
+#[must_use]
+pub fn transpose_band_energies(bins: &[FilteredWaveformBin]) -> [Vec<f32>; 4] {
+    [
+        extract_metric(bins, selector::all_energy),
+        extract_metric(bins, selector::low_energy),
+        extract_metric(bins, selector::mid_energy),
+        extract_metric(bins, selector::high_energy),
+    ]
+}
+
+/// Common selectors for [`extract_metric()`]
+pub mod selector {
+    use crate::FilteredWaveformBin;
+
+    #[must_use]
+    pub fn all_peak(bin: &FilteredWaveformBin) -> f32 {
+        bin.all.peak.to_f32()
+    }
+
+    #[must_use]
+    pub fn all_energy(bin: &FilteredWaveformBin) -> f32 {
+        bin.all.energy.to_f32()
+    }
+
+    #[must_use]
+    pub fn low_peak(bin: &FilteredWaveformBin) -> f32 {
+        bin.low.peak.to_f32()
+    }
+
+    #[must_use]
+    pub fn low_energy(bin: &FilteredWaveformBin) -> f32 {
+        bin.low.energy.to_f32()
+    }
+
+    #[must_use]
+    pub fn mid_peak(bin: &FilteredWaveformBin) -> f32 {
+        bin.mid.peak.to_f32()
+    }
+
+    #[must_use]
+    pub fn mid_energy(bin: &FilteredWaveformBin) -> f32 {
+        bin.mid.energy.to_f32()
+    }
+
+    #[must_use]
+    pub fn high_peak(bin: &FilteredWaveformBin) -> f32 {
+        bin.high.peak.to_f32()
+    }
+
+    #[must_use]
+    pub fn high_energy(bin: &FilteredWaveformBin) -> f32 {
+        bin.high.energy.to_f32()
+    }
+
+    /// See [`FilteredWaveformBin::spectral_flatness()`]
+    #[must_use]
+    pub fn spectral_flatness(bin: &FilteredWaveformBin) -> f32 {
+        bin.spectral_flatness()
+    }
+
+    /// See [`FilteredWaveformBin::warmth()`]
+    #[must_use]
+    pub fn warmth(bin: &FilteredWaveformBin) -> f32 {
+        bin.warmth()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FilteredWaveformBin, WaveformBin, WaveformVal};
+
+    use super::{extract_metric, selector, transpose_band_energies};
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn extract_single_metric() {
+        let bins = [0, 51, 255]
+            .into_iter()
+            .map(|energy| FilteredWaveformBin {
+                low: WaveformBin {
+                    peak: WaveformVal(u8::MAX),
+                    energy: WaveformVal(energy),
+                },
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![0.0, 0.2, 1.0],
+            extract_metric(&bins, selector::low_energy)
+        );
+        assert_eq!(vec![1.0; 3], extract_metric(&bins, selector::low_peak));
+        assert_eq!(
+            vec![0.0; 3],
+            extract_metric(&bins, |bin| bin.all.peak.to_f32())
+        );
+        assert!(extract_metric(&[], selector::all_energy).is_empty());
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn transposed_band_energies() {
+        let band = |energy| WaveformBin {
+            peak: WaveformVal(u8::MAX),
+            energy: WaveformVal(energy),
+        };
+        let bins = [
+            FilteredWaveformBin {
+                all: band(255),
+                low: band(51),
+                mid: band(0),
+                high: band(102),
+            },
+            FilteredWaveformBin {
+                all: band(0),
+                low: band(255),
+                mid: band(51),
+                high: band(0),
+            },
+        ];
+        let [all, low, mid, high] = transpose_band_energies(&bins);
+        assert_eq!(vec![1.0, 0.0], all);
+        assert_eq!(vec![0.2, 1.0], low);
+        assert_eq!(vec![0.0, 0.2], mid);
+        assert_eq!(vec![0.4, 0.0], high);
+        assert!(transpose_band_energies(&[]).iter().all(Vec::is_empty));
+    }
+}
@@ -0,0 +1,173 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Coarse pitch estimation
+
+use crate::{filter::FilteredSample, FilteredWaveformBin, WaveformFilter, WaveformFilterConfig};
+
+/// Maximum spectral flatness of bins with a pitch estimate
+///
+/// Noise-like bins with a flat spectrum have no dominant fundamental
+/// and the zero-crossing rate is meaningless.
+const MAX_SPECTRAL_FLATNESS: f32 = 0.5;
+
+/// Bin with a coarse estimate of its pitch
+#[derive(Debug, Clone, Default)]
+pub struct FilteredWaveformBinWithPitch {
+    pub bin: FilteredWaveformBin,
+
+    /// Estimated fundamental frequency in Hz
+    ///
+    /// Derived from the zero-crossing rate of the combined low and mid
+    /// band, i.e. the fundamental is half the number of zero crossings
+    /// per second. Only a crude estimate that is reasonable for
+    /// monophonic, near-sinusoidal content, e.g. for displaying a rough
+    /// melodic contour.
+    ///
+    /// `None` if the bin is too noisy, i.e. if its
+    /// [spectral flatness](FilteredWaveformBin::spectral_flatness) is
+    /// too high, or if the signal does not cross zero at all.
+    pub pitch_hz: Option<f32>,
+}
+
+/// Zero crossings of signed samples
+#[derive(Debug, Default)]
+struct ZeroCrossingAccumulator {
+    sample_count: u32,
+    crossing_count: u32,
+    last_sign_positive: Option<bool>,
+}
+
+impl ZeroCrossingAccumulator {
+    fn add_sample(&mut self, sample: f32) {
+        self.sample_count += 1;
+        if sample == 0.0 {
+            return;
+        }
+        let sign_positive = sample > 0.0;
+        if self
+            .last_sign_positive
+            .is_some_and(|last_sign_positive| last_sign_positive != sign_positive)
+        {
+            self.crossing_count += 1;
+        }
+        self.last_sign_positive = Some(sign_positive);
+    }
+
+    /// Zero crossings per sample of the current bin
+    ///
+    /// The sign of the last sample is preserved for detecting crossings
+    /// at the bin boundary.
+    fn finish(&mut self) -> Option<f32> {
+        let sample_count = std::mem::take(&mut self.sample_count);
+        let crossing_count = std::mem::take(&mut self.crossing_count);
+        if crossing_count == 0 {
+            return None;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let rate = crossing_count as f32 / sample_count as f32;
+        Some(rate)
+    }
+}
+
+fn with_pitch(
+    bin: FilteredWaveformBin,
+    zero_crossing_rate: Option<f32>,
+    sample_rate_hz: f32,
+) -> FilteredWaveformBinWithPitch {
+    let pitch_hz = zero_crossing_rate
+        .filter(|_| bin.spectral_flatness() <= MAX_SPECTRAL_FLATNESS)
+        .map(|rate| rate * sample_rate_hz / 2.0);
+    FilteredWaveformBinWithPitch { bin, pitch_hz }
+}
+
+/// Variant of [`WaveformFilter`] that also estimates the pitch of each bin
+///
+/// Bins are identical to those of [`WaveformFilter`].
+#[derive(Debug)]
+pub struct PitchWaveformFilter {
+    filter: WaveformFilter,
+    zero_crossings: ZeroCrossingAccumulator,
+}
+
+impl Default for PitchWaveformFilter {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl PitchWaveformFilter {
+    #[must_use]
+    pub fn new(config: WaveformFilterConfig) -> Self {
+        Self {
+            filter: WaveformFilter::new(config),
+            zero_crossings: Default::default(),
+        }
+    }
+
+    pub fn add_sample(&mut self, sample: f32) -> Option<FilteredWaveformBinWithPitch> {
+        // The completed bin doesn't include the new sample. The zero
+        // crossings must also be reset for discarded warmup bins.
+        let zero_crossing_rate = self
+            .filter
+            .is_bin_complete()
+            .then(|| self.zero_crossings.finish());
+        let (next_bin, FilteredSample { low, mid, .. }) = self.filter.add_sample_filtered(sample);
+        self.zero_crossings.add_sample(low + mid);
+        let sample_rate_hz = self.filter.sample_rate_hz();
+        next_bin.map(|bin| with_pitch(bin, zero_crossing_rate.flatten(), sample_rate_hz))
+    }
+
+    #[must_use]
+    pub fn finish(mut self) -> Option<FilteredWaveformBinWithPitch> {
+        let zero_crossing_rate = self.zero_crossings.finish();
+        let Self {
+            filter,
+            zero_crossings: _,
+        } = self;
+        let sample_rate_hz = filter.sample_rate_hz();
+        filter
+            .finish()
+            .map(|bin| with_pitch(bin, zero_crossing_rate, sample_rate_hz))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+
+    use super::PitchWaveformFilter;
+
+    #[test]
+    fn pitch_of_sine_wave() {
+        let mut filter = PitchWaveformFilter::default();
+        let bins = (0..44_100)
+            .map(|i| {
+                #[allow(clippy::cast_precision_loss)]
+                let t = i as f32 / 44_100.0;
+                0.5 * (TAU * 500.0 * t).sin()
+            })
+            .filter_map(|sample| filter.add_sample(sample))
+            .collect::<Vec<_>>();
+        assert!(bins.len() > 100);
+        // Skip the leading bins while the filters are settling
+        let bins = &bins[10..];
+        // Bins contain only a few zero crossings
+        let mut pitch_sum_hz = 0.0;
+        for bin in bins {
+            let pitch_hz = bin.pitch_hz.unwrap();
+            assert!((pitch_hz - 500.0).abs() < 100.0);
+            pitch_sum_hz += pitch_hz;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let mean_pitch_hz = pitch_sum_hz / bins.len() as f32;
+        assert!((mean_pitch_hz - 500.0).abs() < 5.0);
+        assert!(filter.finish().is_some());
+        // Silence
+        let mut filter = PitchWaveformFilter::default();
+        let bins = (0..1_000)
+            .filter_map(|_| filter.add_sample(0.0))
+            .collect::<Vec<_>>();
+        assert!(bins.iter().all(|bin| bin.pitch_hz.is_none()));
+    }
+}
@@ -0,0 +1,107 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Punchiness of transients
+
+use crate::FilteredWaveformBin;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PunchTrackerConfig {
+    /// Number of bins of the baseline
+    ///
+    /// The time constant of the moving average of the `all` energy
+    /// that serves as the baseline. Should cover only a few bins,
+    /// because the baseline must follow the envelope between beats.
+    pub baseline_bins: f32,
+
+    /// Rise of the `all` energy above the baseline for full punchiness
+    pub full_scale_rise: f32,
+}
+
+impl PunchTrackerConfig {
+    pub const DEFAULT: Self = Self {
+        baseline_bins: 8.0,
+        full_scale_rise: 0.25,
+    };
+}
+
+impl Default for PunchTrackerConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Punchiness of consecutive bins
+///
+/// Measures how much the wideband `all` energy of a bin rises above
+/// a short baseline of the preceding bins, e.g. for flashing the
+/// display on kicks and snares. Decaying or steady energy results
+/// in no punchiness at all.
+#[derive(Debug)]
+pub struct PunchTracker {
+    config: PunchTrackerConfig,
+    baseline: Option<f32>,
+}
+
+impl Default for PunchTracker {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl PunchTracker {
+    #[must_use]
+    pub fn new(config: PunchTrackerConfig) -> Self {
+        debug_assert!(config.baseline_bins >= 1.0);
+        debug_assert!(config.full_scale_rise > 0.0);
+        Self {
+            config,
+            baseline: None,
+        }
+    }
+
+    /// Punchiness of the next bin
+    ///
+    /// Returns a value in the range `0..=1`. The first bin has no
+    /// baseline and is never considered as punchy.
+    pub fn track(&mut self, bin: &FilteredWaveformBin) -> f32 {
+        let PunchTrackerConfig {
+            baseline_bins,
+            full_scale_rise,
+        } = self.config;
+        let energy = bin.all.energy.to_f32();
+        let baseline = self.baseline.unwrap_or(energy);
+        self.baseline = Some(baseline + (energy - baseline) / baseline_bins);
+        ((energy - baseline) / full_scale_rise).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::bin_with_all_energy;
+
+    use super::{PunchTracker, PunchTrackerConfig};
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn rising_energy_only() {
+        let mut tracker = PunchTracker::new(PunchTrackerConfig {
+            baseline_bins: 2.0,
+            full_scale_rise: 0.5,
+        });
+        let punchiness = [100, 100, 255, 255, 0, 0, 102]
+            .into_iter()
+            .map(|energy| tracker.track(&bin_with_all_energy(energy)))
+            .collect::<Vec<_>>();
+        assert_eq!(0.0, punchiness[0]);
+        assert_eq!(0.0, punchiness[1]);
+        assert!(punchiness[2] > 0.9);
+        // Still above the baseline, but less punchy
+        assert!(punchiness[3] > 0.0);
+        assert!(punchiness[3] < punchiness[2]);
+        assert_eq!(0.0, punchiness[4]);
+        assert_eq!(0.0, punchiness[5]);
+        assert!(punchiness[6] > 0.0);
+        assert!(punchiness[6] < 1.0);
+    }
+}
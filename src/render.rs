@@ -0,0 +1,312 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+use crate::{resample::resample_ranges, FilteredWaveformBin, LinearRgb};
+
+/// Value that determines the height of a column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaveformHeight {
+    /// The `all` peak
+    Peak,
+
+    /// The `all` energy
+    Energy,
+}
+
+/// Color of a column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaveformFill {
+    /// Spectral color of the band energies
+    ///
+    /// See also: [`FilteredWaveformVal::spectral_rgb_color()`]
+    ///
+    /// [`FilteredWaveformVal::spectral_rgb_color()`]: crate::FilteredWaveformVal::spectral_rgb_color
+    Spectral,
+
+    /// Uniform RGBA color
+    Flat([u8; 4]),
+}
+
+/// Vertical alignment of the columns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaveformLayout {
+    /// Mirrored around the horizontal center line
+    Symmetric,
+
+    /// Growing upwards from the bottom
+    Baseline,
+}
+
+/// Styling of [`render_waveform_rgba()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WaveformStyle {
+    /// RGBA color of the background
+    pub background: [u8; 4],
+    pub height: WaveformHeight,
+    pub fill: WaveformFill,
+    pub layout: WaveformLayout,
+}
+
+impl WaveformStyle {
+    pub const DEFAULT: Self = Self {
+        background: [0, 0, 0, u8::MAX],
+        height: WaveformHeight::Peak,
+        fill: WaveformFill::Spectral,
+        layout: WaveformLayout::Symmetric,
+    };
+}
+
+impl Default for WaveformStyle {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Number of bytes per RGBA pixel
+const RGBA_PIXEL_LEN: usize = 4;
+
+/// Image of the waveform, e.g. for thumbnails
+///
+/// Resamples the bins into `width` columns. Returns the pixels as 8-bit
+/// RGBA with straight (not premultiplied) alpha in row-major order from
+/// top to bottom, i.e. the stride is `4 * width` bytes. Spectral colors
+/// are gamma encoded as sRGB. The image is filled with the background
+/// color if `bins` is empty.
+#[must_use]
+pub fn render_waveform_rgba(
+    bins: &[FilteredWaveformBin],
+    width: usize,
+    height: usize,
+    style: &WaveformStyle,
+) -> Vec<u8> {
+    let mut pixels = vec![0; RGBA_PIXEL_LEN * width * height];
+    render_waveform_rgba_into(bins, width, height, style, &mut pixels);
+    pixels
+}
+
+/// Render a spectral-colored, symmetric waveform into a pixel buffer
+///
+/// The minimal rendering primitive for immediate-mode GUIs. Each pixel
+/// occupies 4 bytes in RGBA order with premultiplied alpha. Rows are
+/// stored from top to bottom without padding, i.e. the stride is
+/// `4 * width` bytes. The background is fully transparent and the
+/// waveform is opaque, its height is determined by the `all` peak.
+///
+/// # Panics
+///
+/// Panics if the length of `out` is not `4 * width * height`.
+pub fn render_to_rgba(bins: &[FilteredWaveformBin], width: usize, height: usize, out: &mut [u8]) {
+    let style = WaveformStyle {
+        background: [0; 4],
+        ..WaveformStyle::DEFAULT
+    };
+    render_waveform_rgba_into(bins, width, height, &style, out);
+}
+
+fn render_waveform_rgba_into(
+    bins: &[FilteredWaveformBin],
+    width: usize,
+    height: usize,
+    style: &WaveformStyle,
+    pixels: &mut [u8],
+) {
+    let WaveformStyle {
+        background,
+        height: column_height,
+        fill,
+        layout,
+    } = *style;
+    let stride = RGBA_PIXEL_LEN * width;
+    assert_eq!(stride * height, pixels.len());
+    for pixel in pixels.chunks_exact_mut(RGBA_PIXEL_LEN) {
+        pixel.copy_from_slice(&background);
+    }
+    if bins.is_empty() {
+        return;
+    }
+    for (column, range) in resample_ranges(bins.len(), width).enumerate() {
+        let bin = FilteredWaveformBin::combine(&bins[range]);
+        let value = match column_height {
+            WaveformHeight::Peak => bin.all.peak,
+            WaveformHeight::Energy => bin.all.energy,
+        };
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_precision_loss)]
+        #[allow(clippy::cast_sign_loss)]
+        let extent = (value.to_f32() * height as f32).round() as usize;
+        let top = match layout {
+            WaveformLayout::Symmetric => (height - extent) / 2,
+            WaveformLayout::Baseline => height - extent,
+        };
+        let color = match fill {
+            WaveformFill::Spectral => {
+                let [red, green, blue] = bin.energy().spectral_rgb_color().to_srgb().to_rgb8();
+                [red, green, blue, u8::MAX]
+            }
+            WaveformFill::Flat(color) => color,
+        };
+        for row in top..top + extent {
+            let offset = row * stride + column * RGBA_PIXEL_LEN;
+            pixels[offset..offset + RGBA_PIXEL_LEN].copy_from_slice(&color);
+        }
+    }
+}
+
+/// Spectral color at a fractional bin position
+///
+/// Interpolates the band energies of the two neighboring bins, see
+/// [`FilteredWaveformVal::lerp()`], and returns their spectral color,
+/// e.g. for a playhead that moves smoothly between bins. The integer
+/// positions refer to the bins themselves. Positions outside of the
+/// bins are clamped. Returns black if `bins` is empty.
+///
+/// [`FilteredWaveformVal::lerp()`]: crate::FilteredWaveformVal::lerp
+#[must_use]
+pub fn color_at(bins: &[FilteredWaveformBin], pos: f32) -> LinearRgb {
+    let Some(last_index) = bins.len().checked_sub(1) else {
+        return LinearRgb::default();
+    };
+    #[allow(clippy::cast_precision_loss)]
+    let pos = pos.clamp(0.0, last_index as f32);
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    let index = (pos.floor() as usize).min(last_index);
+    let next_index = (index + 1).min(last_index);
+    bins[index]
+        .energy()
+        .lerp(bins[next_index].energy(), pos.fract())
+        .spectral_rgb_color()
+}
+
+const SPARKLINE_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Textual waveform for terminals and logs
+///
+/// Resamples the bins into `width` columns and maps the maximum `all`
+/// peak of each column to a block character. Returns an empty string
+/// if `bins` is empty.
+#[must_use]
+pub fn bins_to_sparkline(bins: &[FilteredWaveformBin], width: usize) -> String {
+    if bins.is_empty() {
+        return String::new();
+    }
+    resample_ranges(bins.len(), width)
+        .map(|range| {
+            let peak = bins[range]
+                .iter()
+                .map(|bin| bin.all.peak)
+                .max()
+                .unwrap_or_default();
+            let index =
+                usize::from(u8::from(peak)) * SPARKLINE_CHARS.len() / (usize::from(u8::MAX) + 1);
+            SPARKLINE_CHARS[index]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{FilteredWaveformBin, LinearRgb, WaveformBin, WaveformVal};
+
+    use super::{
+        bins_to_sparkline, color_at, render_to_rgba, render_waveform_rgba, WaveformFill,
+        WaveformHeight, WaveformLayout, WaveformStyle,
+    };
+
+    #[test]
+    fn sparkline() {
+        assert_eq!("", bins_to_sparkline(&[], 10));
+        let bins = [0, 64, 128, 255]
+            .into_iter()
+            .map(|peak| FilteredWaveformBin {
+                all: WaveformBin {
+                    peak: WaveformVal(peak),
+                    energy: WaveformVal(0),
+                },
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!("▁▃▅█", bins_to_sparkline(&bins, 4));
+        assert_eq!("▃█", bins_to_sparkline(&bins, 2));
+        assert_eq!("▁▁▃▃▅▅██", bins_to_sparkline(&bins, 8));
+    }
+
+    #[test]
+    fn waveform_rgba() {
+        const BLACK: [u8; 4] = [0, 0, 0, u8::MAX];
+        const WHITE: [u8; 4] = [u8::MAX; 4];
+        let style = WaveformStyle {
+            background: BLACK,
+            height: WaveformHeight::Peak,
+            fill: WaveformFill::Flat(WHITE),
+            layout: WaveformLayout::Symmetric,
+        };
+        assert_eq!(BLACK.repeat(6), render_waveform_rgba(&[], 2, 3, &style));
+        let bins = [255, 85]
+            .into_iter()
+            .map(|peak| FilteredWaveformBin {
+                all: WaveformBin {
+                    peak: WaveformVal(peak),
+                    energy: WaveformVal(0),
+                },
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let pixels = render_waveform_rgba(&bins, 2, 3, &style);
+        assert_eq!([WHITE, BLACK, WHITE, WHITE, WHITE, BLACK].concat(), pixels);
+        let style = WaveformStyle {
+            layout: WaveformLayout::Baseline,
+            ..style
+        };
+        let pixels = render_waveform_rgba(&bins, 2, 3, &style);
+        assert_eq!([WHITE, BLACK, WHITE, BLACK, WHITE, WHITE].concat(), pixels);
+    }
+
+    #[test]
+    fn rgba_buffer() {
+        let bin = FilteredWaveformBin {
+            all: WaveformBin {
+                peak: WaveformVal(u8::MAX),
+                energy: WaveformVal(u8::MAX),
+            },
+            high: WaveformBin {
+                peak: WaveformVal(u8::MAX),
+                energy: WaveformVal(u8::MAX),
+            },
+            ..Default::default()
+        };
+        let mut out = vec![1; 4 * 3 * 2];
+        render_to_rgba(&[], 3, 2, &mut out);
+        assert!(out.iter().all(|&byte| byte == 0));
+        render_to_rgba(&[bin], 3, 2, &mut out);
+        assert_eq!([0, 0, u8::MAX, u8::MAX].repeat(6), out);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn interpolated_color() {
+        assert_eq!(LinearRgb::default(), color_at(&[], 1.0));
+        let low = FilteredWaveformBin {
+            low: WaveformBin {
+                peak: WaveformVal(u8::MAX),
+                energy: WaveformVal(u8::MAX),
+            },
+            ..Default::default()
+        };
+        let high = FilteredWaveformBin {
+            high: WaveformBin {
+                peak: WaveformVal(u8::MAX),
+                energy: WaveformVal(u8::MAX),
+            },
+            ..Default::default()
+        };
+        let bins = [low.clone(), high.clone()];
+        assert_eq!(low.energy().spectral_rgb_color(), color_at(&bins, -1.0));
+        assert_eq!(low.energy().spectral_rgb_color(), color_at(&bins, 0.0));
+        assert_eq!(high.energy().spectral_rgb_color(), color_at(&bins, 1.0));
+        assert_eq!(high.energy().spectral_rgb_color(), color_at(&bins, 2.5));
+        let LinearRgb([red, green, blue]) = color_at(&bins, 0.5);
+        assert_eq!(red, blue);
+        assert_eq!(0.0, green);
+    }
+}
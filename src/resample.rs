@@ -0,0 +1,43 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+use std::ops::Range;
+
+/// Split `len` bins into `width` evenly distributed, non-empty ranges
+///
+/// Consecutive ranges are adjacent if `width <= len`. Otherwise bins
+/// are repeated, i.e. ranges are not disjoint.
+pub(crate) fn resample_ranges(len: usize, width: usize) -> impl Iterator<Item = Range<usize>> {
+    debug_assert!(len > 0 || width == 0);
+    (0..width).map(move |column| {
+        let start = column * len / width;
+        let end = ((column + 1) * len / width).max(start + 1);
+        start..end
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resample_ranges;
+
+    #[test]
+    fn resample_ranges_cover_all_bins() {
+        for len in 1..50 {
+            for width in 1..50 {
+                let ranges = resample_ranges(len, width).collect::<Vec<_>>();
+                assert_eq!(width, ranges.len());
+                assert_eq!(0, ranges.first().unwrap().start);
+                assert_eq!(len, ranges.last().unwrap().end);
+                for range in &ranges {
+                    assert!(!range.is_empty());
+                }
+                for pair in ranges.windows(2) {
+                    assert!(pair[0].start <= pair[1].start);
+                    if width <= len {
+                        assert_eq!(pair[0].end, pair[1].start);
+                    }
+                }
+            }
+        }
+    }
+}
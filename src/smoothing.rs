@@ -0,0 +1,93 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Smoothing of colors across bins
+
+use crate::{FilteredWaveformBin, LinearRgb};
+
+/// Smoothed spectral colors of consecutive bins
+///
+/// Maintains an exponential moving average of the hue, i.e. of the
+/// spectral color with full brightness, see
+/// [`FilteredWaveformVal::spectral_rgb_color()`](crate::FilteredWaveformVal::spectral_rgb_color).
+/// The brightness of each color is limited by the `all` energy of
+/// the current bin like for
+/// [`FilteredWaveformVal::spectral_rgb_color_all()`](crate::FilteredWaveformVal::spectral_rgb_color_all),
+/// i.e. only the hue transitions are smoothed while peaks stay crisp.
+#[derive(Debug)]
+pub struct ColorSmoother {
+    factor: f32,
+    hue: Option<[f32; 3]>,
+}
+
+impl ColorSmoother {
+    /// Create a new smoother
+    ///
+    /// The `factor` in the range `0..=1` is the weight of the current
+    /// bin. Smaller values result in smoother transitions and `1.0`
+    /// disables smoothing.
+    #[must_use]
+    pub fn new(factor: f32) -> Self {
+        debug_assert!(factor > 0.0);
+        debug_assert!(factor <= 1.0);
+        Self { factor, hue: None }
+    }
+
+    /// Smoothed color of the next bin
+    pub fn smooth(&mut self, bin: &FilteredWaveformBin) -> LinearRgb {
+        let energy = bin.energy();
+        let LinearRgb(color) = energy.spectral_rgb_color_all();
+        let brightness = color.into_iter().fold(0.0, f32::max);
+        if brightness == 0.0 {
+            // Silence has no hue
+            return LinearRgb([0.0; 3]);
+        }
+        let LinearRgb(current_hue) = energy.spectral_rgb_color();
+        let hue = match &mut self.hue {
+            Some(hue) => {
+                for (smoothed, current) in hue.iter_mut().zip(current_hue) {
+                    *smoothed += self.factor * (current - *smoothed);
+                }
+                *hue
+            }
+            None => *self.hue.insert(current_hue),
+        };
+        let max = hue.into_iter().fold(0.0, f32::max);
+        debug_assert!(max > 0.0);
+        LinearRgb(hue.map(|component| component / max * brightness))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_util::bin_with_energies, FilteredWaveformBin, LinearRgb};
+
+    use super::ColorSmoother;
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn smooth_hue_transitions() {
+        let mut smoother = ColorSmoother::new(0.5);
+        assert_eq!(
+            LinearRgb([1.0, 0.0, 0.0]),
+            smoother.smooth(&bin_with_energies(255, 255, 0, 0))
+        );
+        // Halfway from red to green
+        assert_eq!(
+            LinearRgb([1.0, 1.0, 0.0]),
+            smoother.smooth(&bin_with_energies(255, 0, 255, 0))
+        );
+        let LinearRgb([red, green, blue]) = smoother.smooth(&bin_with_energies(255, 0, 255, 0));
+        assert!(red < green);
+        assert_eq!(1.0, green);
+        assert_eq!(0.0, blue);
+        // The brightness is not smoothed
+        let LinearRgb([red, green, _]) = smoother.smooth(&bin_with_energies(255, 0, 51, 0));
+        assert!(red < green);
+        assert!((green - 0.2).abs() < 1e-6);
+        assert_eq!(
+            LinearRgb([0.0; 3]),
+            smoother.smooth(&FilteredWaveformBin::default())
+        );
+    }
+}
@@ -0,0 +1,118 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Bins from magnitude spectra
+
+use crate::{
+    FilteredWaveformBin, FilteredWaveformBinF32, ThreeBandFilterFreqConfig, WaveformBinF32,
+};
+
+/// Accumulated magnitudes of a single band
+#[derive(Debug, Default)]
+struct SpectrumAccumulator {
+    magnitude_sum: f64,
+    magnitude_square_sum: f64,
+}
+
+impl SpectrumAccumulator {
+    fn add_magnitude(&mut self, magnitude: f32) {
+        let magnitude = f64::from(magnitude.abs());
+        self.magnitude_sum += magnitude;
+        self.magnitude_square_sum += magnitude * magnitude;
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn finish(self) -> WaveformBinF32 {
+        let Self {
+            magnitude_sum,
+            magnitude_square_sum,
+        } = self;
+        let (bin, _) = WaveformBinF32 {
+            peak: magnitude_sum as f32,
+            energy: magnitude_square_sum.sqrt() as f32,
+        }
+        .clamp_reporting();
+        bin
+    }
+}
+
+/// Create a bin from a magnitude spectrum, e.g. a frame of an STFT
+///
+/// An alternative to time-domain filtering for spectrogram-based pipelines.
+/// The magnitude of each frequency bin is the amplitude of the corresponding
+/// sinusoidal component, i.e. a full-scale sine has the magnitude `1.0`.
+///
+/// The magnitudes are assigned to the bands according to the crossover
+/// frequencies. Like the filters, the bands overlap, i.e. the mid band
+/// covers the range `low_hp_hz..=high_lp_hz`. The peak of each band is the
+/// sum of its magnitudes, which is an upper bound of the actual peak. The
+/// energy is the root of the sum of the squared magnitudes, consistent with
+/// the `SQRT_2` scaling of the RMS by [`WaveformFilter`](crate::WaveformFilter).
+///
+/// # Panics
+///
+/// Panics if `magnitudes` and `bin_freqs` differ in length.
+#[must_use]
+pub fn from_spectrum(
+    magnitudes: &[f32],
+    bin_freqs: &[f32],
+    config: &ThreeBandFilterFreqConfig,
+) -> FilteredWaveformBin {
+    assert_eq!(magnitudes.len(), bin_freqs.len());
+    let ThreeBandFilterFreqConfig {
+        low_lp_hz,
+        low_hp_hz,
+        high_lp_hz,
+        high_hp_hz,
+    } = *config;
+    let mut all = SpectrumAccumulator::default();
+    let mut low = SpectrumAccumulator::default();
+    let mut mid = SpectrumAccumulator::default();
+    let mut high = SpectrumAccumulator::default();
+    for (&magnitude, &freq_hz) in magnitudes.iter().zip(bin_freqs) {
+        all.add_magnitude(magnitude);
+        if freq_hz <= low_lp_hz {
+            low.add_magnitude(magnitude);
+        }
+        if (low_hp_hz..=high_lp_hz).contains(&freq_hz) {
+            mid.add_magnitude(magnitude);
+        }
+        if freq_hz >= high_hp_hz {
+            high.add_magnitude(magnitude);
+        }
+    }
+    FilteredWaveformBinF32 {
+        all: all.finish(),
+        low: low.finish(),
+        mid: mid.finish(),
+        high: high.finish(),
+    }
+    .quantize()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ThreeBandFilterFreqConfig, WaveformVal};
+
+    use super::from_spectrum;
+
+    #[test]
+    fn bands_from_spectrum() {
+        let config = ThreeBandFilterFreqConfig::DEFAULT;
+        assert!(from_spectrum(&[], &[], &config).is_silent());
+        let bin_freqs = [100.0, 180.0, 1000.0, 1400.0, 5000.0];
+        let magnitudes = [0.5, 0.0, 0.25, 0.0, 1.0];
+        let bin = from_spectrum(&magnitudes, &bin_freqs, &config);
+        assert_eq!(WaveformVal(u8::MAX), bin.all.peak);
+        assert_eq!(WaveformVal(128), bin.low.peak);
+        assert_eq!(WaveformVal(128), bin.low.energy);
+        assert_eq!(WaveformVal(64), bin.mid.peak);
+        assert_eq!(WaveformVal(64), bin.mid.energy);
+        assert_eq!(WaveformVal(u8::MAX), bin.high.energy);
+        // Overlapping bands
+        let bin = from_spectrum(&[0.5], &[180.0], &config);
+        assert_eq!(WaveformVal(128), bin.low.peak);
+        assert_eq!(WaveformVal(128), bin.mid.peak);
+        assert!(bin.high.is_silent());
+    }
+}
@@ -0,0 +1,130 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Persistent caching of computed waveforms
+
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, Hash},
+};
+
+use crate::{compute_waveform, FilteredWaveformBin, WaveformFilterConfig};
+
+/// Storage backend of a [`WaveformStore`]
+///
+/// Implemented for [`HashMap`] for in-memory storage. Implement this
+/// trait for other backends, e.g. for storing the waveforms on disk by
+/// [`write_waveform_file()`](crate::write_waveform_file).
+pub trait WaveformStorage<K> {
+    /// Load the stored config and bins
+    fn load(&mut self, key: &K) -> Option<(WaveformFilterConfig, Vec<FilteredWaveformBin>)>;
+
+    /// Store the config and bins, replacing any previous entry
+    fn store(&mut self, key: K, config: WaveformFilterConfig, bins: &[FilteredWaveformBin]);
+}
+
+impl<K, H> WaveformStorage<K> for HashMap<K, (WaveformFilterConfig, Vec<FilteredWaveformBin>), H>
+where
+    K: Eq + Hash,
+    H: BuildHasher,
+{
+    fn load(&mut self, key: &K) -> Option<(WaveformFilterConfig, Vec<FilteredWaveformBin>)> {
+        self.get(key).cloned()
+    }
+
+    fn store(&mut self, key: K, config: WaveformFilterConfig, bins: &[FilteredWaveformBin]) {
+        self.insert(key, (config, bins.to_vec()));
+    }
+}
+
+/// Computed waveforms of tracks, keyed by the identity of the track
+///
+/// The key is supplied by the caller, e.g. a hash of the file contents.
+/// Stored bins are only reused if they have been computed with the
+/// same config. Otherwise the bins are recomputed and replaced.
+#[derive(Debug, Default)]
+pub struct WaveformStore<K, S = HashMap<K, (WaveformFilterConfig, Vec<FilteredWaveformBin>)>> {
+    storage: S,
+    _key: std::marker::PhantomData<fn(K)>,
+}
+
+impl<K, S> WaveformStore<K, S>
+where
+    S: WaveformStorage<K>,
+{
+    #[must_use]
+    pub const fn new(storage: S) -> Self {
+        Self {
+            storage,
+            _key: std::marker::PhantomData,
+        }
+    }
+
+    #[must_use]
+    pub const fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    #[must_use]
+    pub fn into_storage(self) -> S {
+        self.storage
+    }
+
+    /// Get the stored bins or compute and store them
+    ///
+    /// The samples are only loaded from `samples` if the bins need to
+    /// be computed, see [`compute_waveform()`].
+    pub fn get_or_compute<T: AsRef<[f32]>>(
+        &mut self,
+        key: K,
+        config: &WaveformFilterConfig,
+        samples: impl FnOnce() -> T,
+    ) -> Vec<FilteredWaveformBin> {
+        if let Some((stored_config, bins)) = self.storage.load(&key) {
+            if stored_config == *config {
+                return bins;
+            }
+        }
+        let bins = compute_waveform(config.clone(), samples().as_ref());
+        self.storage.store(key, config.clone(), &bins);
+        bins
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crate::WaveformFilterConfig;
+
+    use super::WaveformStore;
+
+    #[test]
+    fn compute_once_per_key_and_config() {
+        let mut store = WaveformStore::<&str>::default();
+        let samples = (0..10_000)
+            .map(|i| if i % 20 < 10 { 0.5 } else { -0.5 })
+            .collect::<Vec<_>>();
+        let load_count = Cell::new(0);
+        let load_samples = || {
+            load_count.set(load_count.get() + 1);
+            samples.as_slice()
+        };
+        let config = WaveformFilterConfig::DEFAULT;
+        let bins = store.get_or_compute("track", &config, load_samples);
+        assert!(!bins.is_empty());
+        assert_eq!(1, load_count.get());
+        let cached_bins = store.get_or_compute("track", &config, load_samples);
+        assert_eq!(bins.len(), cached_bins.len());
+        assert_eq!(1, load_count.get());
+        store.get_or_compute("other track", &config, load_samples);
+        assert_eq!(2, load_count.get());
+        // Recomputed for a different config
+        let mut coarse_config = config;
+        coarse_config.bins_per_sec /= 2.0;
+        let coarse_bins = store.get_or_compute("track", &coarse_config, load_samples);
+        assert_eq!(3, load_count.get());
+        assert!(coarse_bins.len() < bins.len());
+        assert_eq!(2, store.storage().len());
+    }
+}
@@ -0,0 +1,32 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Fixtures shared by the tests of multiple modules
+
+use crate::{FilteredWaveformBin, WaveformBin, WaveformVal};
+
+/// A bin with equal peak and energy values in each band
+pub(crate) const fn bin_with_energies(all: u8, low: u8, mid: u8, high: u8) -> FilteredWaveformBin {
+    const fn bin(energy: u8) -> WaveformBin {
+        WaveformBin {
+            peak: WaveformVal(energy),
+            energy: WaveformVal(energy),
+        }
+    }
+    FilteredWaveformBin {
+        all: bin(all),
+        low: bin(low),
+        mid: bin(mid),
+        high: bin(high),
+    }
+}
+
+/// A bin with only the `all` band
+pub(crate) const fn bin_with_all_energy(energy: u8) -> FilteredWaveformBin {
+    bin_with_energies(energy, 0, 0, 0)
+}
+
+/// A bin without the `all` band
+pub(crate) const fn bin_with_band_energies(low: u8, mid: u8, high: u8) -> FilteredWaveformBin {
+    bin_with_energies(0, low, mid, high)
+}
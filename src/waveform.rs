@@ -1,6 +1,11 @@
 // SPDX-FileCopyrightText: The audio-viz authors
 // SPDX-License-Identifier: MPL-2.0
 
+use crate::{LinearRgb, ThreeBandFilterFreqConfig};
+
+/// Parameter of the µ-law companding
+const MU_LAW_MU: f32 = 255.0;
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Default)]
 #[repr(transparent)]
 pub struct WaveformVal(pub u8);
@@ -9,6 +14,15 @@ impl WaveformVal {
     const MIN_VAL: u8 = u8::MIN;
     const MAX_VAL: u8 = u8::MAX;
 
+    /// Quantize a value in the range `0..=1`
+    ///
+    /// The range is divided into 256 buckets of uniform width `1/256`.
+    /// The value `k` represents the half-open interval `k/256..(k+1)/256`.
+    /// Only the top bucket is closed, i.e. it also includes `1.0`. Values
+    /// greater than `1.0` are clamped.
+    ///
+    /// The inverse mapping [`Self::to_f32()`] maps the bottom bucket to `0.0`
+    /// and the top bucket to `1.0`. This guarantees a lossless round trip.
     pub(crate) fn from_f32(val: f32) -> Self {
         debug_assert!(val >= f32::from(Self::MIN_VAL));
         let mapped = (val * (f32::from(Self::MAX_VAL) + 1.0)).min(f32::from(Self::MAX_VAL));
@@ -24,10 +38,70 @@ impl WaveformVal {
         f32::from(self.0) / f32::from(Self::MAX_VAL)
     }
 
+    /// Quantize a value in the range `0..=1` with µ-law companding
+    ///
+    /// An alternative to the linear quantization with `µ = 255`. The values
+    /// are spaced logarithmically, i.e. the resolution is much finer for
+    /// quiet and coarser for loud values. Values greater than `1.0` are
+    /// clamped.
+    ///
+    /// The inverse mapping is [`Self::to_f32_mu_law()`]. Companded and
+    /// linear values must not be mixed.
+    #[must_use]
+    pub fn from_f32_mu_law(val: f32) -> Self {
+        debug_assert!(val >= 0.0);
+        let companded = (MU_LAW_MU * val.min(1.0)).ln_1p() / MU_LAW_MU.ln_1p();
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        Self((companded * f32::from(Self::MAX_VAL)).round() as u8)
+    }
+
+    /// Inverse of [`Self::from_f32_mu_law()`]
+    #[must_use]
+    pub fn to_f32_mu_law(self) -> f32 {
+        let companded = self.to_f32();
+        ((companded * MU_LAW_MU.ln_1p()).exp_m1() / MU_LAW_MU).min(1.0)
+    }
+
+    /// Level in decibels relative to full scale
+    ///
+    /// Returns negative infinity for zero.
+    #[must_use]
+    pub fn to_db(self) -> f32 {
+        20.0 * self.to_f32().log10()
+    }
+
+    /// Quantize a level in decibels relative to full scale
+    ///
+    /// Levels at or below `floor_db` map to zero and levels at or above
+    /// 0 dB map to full scale. The inverse of [`Self::to_db()`] for all
+    /// levels above the floor.
+    #[must_use]
+    pub fn from_db(db: f32, floor_db: f32) -> Self {
+        debug_assert!(floor_db < 0.0);
+        if db.is_nan() || db <= floor_db {
+            return Self(Self::MIN_VAL);
+        }
+        Self::from_f32(10.0f32.powf(db.min(0.0) / 20.0))
+    }
+
     #[must_use]
     pub const fn is_zero(self) -> bool {
         self.0 == 0
     }
+
+    /// Linear interpolation
+    ///
+    /// Interpolates between `self` (`t = 0.0`) and `other` (`t = 1.0`)
+    /// in `f32` space and quantizes the result. The parameter `t`
+    /// is clamped to the range `0..=1`.
+    #[must_use]
+    pub fn lerp(self, other: Self, t: f32) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        let start = self.to_f32();
+        let end = other.to_f32();
+        Self::from_f32(start + (end - start) * t)
+    }
 }
 
 impl From<WaveformVal> for u8 {
@@ -45,41 +119,216 @@ pub struct FilteredWaveformVal {
 }
 
 impl FilteredWaveformVal {
+    /// Linear interpolation of all values
+    ///
+    /// See also: [`WaveformVal::lerp()`]
+    #[must_use]
+    pub fn lerp(self, other: Self, t: f32) -> Self {
+        Self {
+            all: self.all.lerp(other.all, t),
+            low: self.low.lerp(other.low, t),
+            mid: self.mid.lerp(other.mid, t),
+            high: self.high.lerp(other.high, t),
+        }
+    }
+
     /// RGB color with full brightness
     #[must_use]
-    pub fn spectral_rgb_color(self) -> (f32, f32, f32) {
+    pub fn spectral_rgb_color(self) -> LinearRgb {
         self.spectral_rgb_color_normalized(0.0)
     }
 
     /// RGB color with brightness limited by [`Self::all`]
     #[must_use]
-    pub fn spectral_rgb_color_all(self) -> (f32, f32, f32) {
+    pub fn spectral_rgb_color_all(self) -> LinearRgb {
         self.spectral_rgb_color_normalized(self.all.to_f32())
     }
 
+    /// RGB color with full brightness and cross-blended neighboring bands
+    ///
+    /// Each color component also receives a fraction of the energy of the
+    /// neighboring bands. The fraction is derived from the overlap of the
+    /// bands at the respective crossover, i.e. from the ratio between
+    /// `low_lp_hz` and `low_hp_hz` or between `high_lp_hz` and `high_hp_hz`:
+    /// half of the overlap in octaves, but at most `0.5`. The colors then
+    /// change more gradually when the content sweeps across a crossover.
     #[must_use]
-    fn spectral_rgb_color_normalized(self, max: f32) -> (f32, f32, f32) {
+    pub fn spectral_rgb_color_crossfaded(
+        self,
+        filter_freqs: &ThreeBandFilterFreqConfig,
+    ) -> LinearRgb {
         let Self {
             all: _,
             low,
             mid,
             high,
         } = self;
+        let ThreeBandFilterFreqConfig {
+            low_lp_hz,
+            low_hp_hz,
+            high_lp_hz,
+            high_hp_hz,
+        } = *filter_freqs;
+        let blend_weight = |lp_hz: f32, hp_hz: f32| ((lp_hz / hp_hz).log2() / 2.0).clamp(0.0, 0.5);
+        let low_mid_weight = blend_weight(low_lp_hz, low_hp_hz);
+        let mid_high_weight = blend_weight(high_lp_hz, high_hp_hz);
         let low = low.to_f32();
         let mid = mid.to_f32();
         let high = high.to_f32();
-        // The `max` value is used to control the brightness of the resulting color.
-        // Otherwise we would only reach the edges of the RGB space with one component
-        // always maxed out.
-        let denom = max.max(low).max(mid).max(high);
-        if denom == 0.0 {
-            return (0.0, 0.0, 0.0);
+        normalized_rgb_color(
+            low + low_mid_weight * mid,
+            mid + low_mid_weight * low + mid_high_weight * high,
+            high + mid_high_weight * mid,
+            0.0,
+        )
+    }
+
+    /// RGB color with a hard brightness cap
+    ///
+    /// The band values are divided by `max` and each component is
+    /// clamped to `1.0`. In contrast to [`Self::spectral_rgb_color_all()`]
+    /// a band that exceeds `max` does not override the cap by becoming
+    /// the normalization reference, i.e. the brightness of the other
+    /// components stays proportional to `max`. Returns black if `max`
+    /// is not positive.
+    #[must_use]
+    pub fn spectral_rgb_color_capped(self, max: f32) -> LinearRgb {
+        let Self {
+            all: _,
+            low,
+            mid,
+            high,
+        } = self;
+        if max <= 0.0 {
+            return LinearRgb([0.0; 3]);
         }
-        let red = low / denom;
-        let green = mid / denom;
-        let blue = high / denom;
-        (red, green, blue)
+        LinearRgb([low, mid, high].map(|val| (val.to_f32() / max).min(1.0)))
+    }
+
+    /// RGB color and grayscale value with brightness limited by [`Self::all`]
+    ///
+    /// The grayscale value is the luminance of the color, see
+    /// [`LinearRgb::luminance()`]. For rendering both a colored and
+    /// a monochrome waveform at once.
+    #[must_use]
+    pub fn spectral_rgb_color_all_with_luminance(self) -> (LinearRgb, f32) {
+        let color = self.spectral_rgb_color_all();
+        (color, color.luminance())
+    }
+
+    /// CIE L*a*b* color with brightness limited by `max`
+    ///
+    /// The spectral RGB color is considered as linear sRGB and converted
+    /// into the perceptually uniform CIE L*a*b* color space. Colors should
+    /// be interpolated in this color space and then converted back into
+    /// RGB by [`lab_to_linear_rgb()`](crate::lab_to_linear_rgb).
+    #[must_use]
+    pub fn spectral_lab_color(self, max: f32) -> (f32, f32, f32) {
+        crate::linear_rgb_to_lab(self.spectral_rgb_color_normalized(max))
     }
+
+    /// RGB color with full brightness and perceptually weighted bands
+    ///
+    /// The bands are weighted before forming the color, see
+    /// [`SpectralColorWeights`].
+    #[must_use]
+    pub fn spectral_rgb_color_weighted(self, weights: &SpectralColorWeights) -> LinearRgb {
+        let Self {
+            all: _,
+            low,
+            mid,
+            high,
+        } = self;
+        let SpectralColorWeights {
+            low: low_weight,
+            mid: mid_weight,
+            high: high_weight,
+        } = *weights;
+        normalized_rgb_color(
+            low.to_f32() * low_weight,
+            mid.to_f32() * mid_weight,
+            high.to_f32() * high_weight,
+            0.0,
+        )
+    }
+
+    #[must_use]
+    fn spectral_rgb_color_normalized(self, max: f32) -> LinearRgb {
+        let Self {
+            all: _,
+            low,
+            mid,
+            high,
+        } = self;
+        normalized_rgb_color(low.to_f32(), mid.to_f32(), high.to_f32(), max)
+    }
+}
+
+/// Perceptual weights of the bands for coloring
+///
+/// Hearing is not equally sensitive to all frequencies. The RGB components
+/// of the spectral color are weighted accordingly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpectralColorWeights {
+    pub low: f32,
+    pub mid: f32,
+    pub high: f32,
+}
+
+impl SpectralColorWeights {
+    /// Loosely follows the equal-loudness contours
+    ///
+    /// Emphasizes the mid band where hearing is most sensitive while
+    /// attenuating the low band the most.
+    pub const DEFAULT: Self = Self {
+        low: 0.5,
+        mid: 1.0,
+        high: 0.8,
+    };
+}
+
+impl Default for SpectralColorWeights {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+fn normalized_rgb_color(low: f32, mid: f32, high: f32, max: f32) -> LinearRgb {
+    // The `max` value is used to control the brightness of the resulting color.
+    // Otherwise we would only reach the edges of the RGB space with one component
+    // always maxed out.
+    let denom = max.max(low).max(mid).max(high);
+    if denom == 0.0 {
+        return LinearRgb([0.0; 3]);
+    }
+    let red = low / denom;
+    let green = mid / denom;
+    let blue = high / denom;
+    LinearRgb([red, green, blue])
+}
+
+/// Frequency band
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Band {
+    Low,
+    Mid,
+    High,
+}
+
+/// The band with the highest energy and its energy
+///
+/// Ties are resolved in favor of the lower band.
+pub(crate) fn max_band_energy<T: PartialOrd>(low: T, mid: T, high: T) -> (Band, T) {
+    [(Band::Mid, mid), (Band::High, high)].into_iter().fold(
+        (Band::Low, low),
+        |(max_band, max_energy), (band, energy)| {
+            if energy > max_energy {
+                (band, energy)
+            } else {
+                (max_band, max_energy)
+            }
+        },
+    )
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -91,6 +340,498 @@ pub struct WaveformBin {
     pub energy: WaveformVal,
 }
 
+impl WaveformBin {
+    /// Quantize a pair of peak and energy values
+    ///
+    /// Both values are clamped into the range `0..=1`. `NaN` values
+    /// are mapped to `0.0`.
+    #[must_use]
+    pub fn from_f32s(peak: f32, energy: f32) -> Self {
+        let clamp = |val: f32| {
+            if val.is_nan() {
+                0.0
+            } else {
+                val.clamp(0.0, 1.0)
+            }
+        };
+        Self {
+            peak: WaveformVal::from_f32(clamp(peak)),
+            energy: WaveformVal::from_f32(clamp(energy)),
+        }
+    }
+
+    /// Aggregate multiple bins into a single bin
+    ///
+    /// Uses the maximum of all peak values and the RMS of all energy
+    /// values, i.e. preserves both the peak and the energy. Returns the
+    /// [`Default`] value if `bins` is empty.
+    #[must_use]
+    pub fn combine(bins: &[Self]) -> Self {
+        Self::combine_iter(bins.iter().copied())
+    }
+
+    fn combine_iter(bins: impl IntoIterator<Item = Self>) -> Self {
+        let mut count = 0u32;
+        let mut peak = WaveformVal::default();
+        let mut energy_square_sum = 0.0f32;
+        for bin in bins {
+            count += 1;
+            peak = peak.max(bin.peak);
+            let energy = bin.energy.to_f32();
+            energy_square_sum += energy * energy;
+        }
+        if count == 0 {
+            return Self::default();
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let energy = (energy_square_sum / count as f32).sqrt();
+        Self {
+            peak,
+            energy: WaveformVal::from_f32(energy),
+        }
+    }
+
+    /// Mix two bins with an equal-power crossfade
+    ///
+    /// Mixes `self` (`t = 0.0`) and `other` (`t = 1.0`) like the samples
+    /// of two uncorrelated signals with the gains `cos(t * PI / 2)` and
+    /// `sin(t * PI / 2)`, i.e. the energy of two bins with equal energies
+    /// stays constant. The peak is the upper bound of the mixed peaks.
+    /// The parameter `t` is clamped to the range `0..=1`.
+    #[must_use]
+    pub fn mix(self, other: Self, t: f32) -> Self {
+        let (other_gain, self_gain) = (t.clamp(0.0, 1.0) * std::f32::consts::FRAC_PI_2).sin_cos();
+        // Rounding errors might result in slightly negative gains
+        let self_gain = self_gain.max(0.0);
+        let peak = self_gain * self.peak.to_f32() + other_gain * other.peak.to_f32();
+        let self_energy = self_gain * self.energy.to_f32();
+        let other_energy = other_gain * other.energy.to_f32();
+        let energy = (self_energy * self_energy + other_energy * other_energy).sqrt();
+        Self {
+            peak: WaveformVal::from_f32(peak),
+            energy: WaveformVal::from_f32(energy),
+        }
+    }
+
+    /// Check if both peak and energy are zero
+    #[must_use]
+    pub const fn is_silent(&self) -> bool {
+        self.peak.is_zero() && self.energy.is_zero()
+    }
+
+    /// Pack peak and energy into a single byte
+    ///
+    /// The peak is stored in the high nibble and the energy in the low
+    /// nibble. Both values lose precision, i.e. only the upper 4 bits are
+    /// preserved. A [`FilteredWaveformBin`] thus fits into 4 bytes.
+    #[must_use]
+    pub const fn to_packed_byte(&self) -> u8 {
+        (self.peak.0 & 0xf0) | (self.energy.0 >> 4)
+    }
+
+    /// Unpack a byte created by [`Self::to_packed_byte()`]
+    ///
+    /// The 4-bit values are expanded to the full range, i.e. the round
+    /// trip deviates by at most 15 from the original values.
+    #[must_use]
+    pub const fn from_packed_byte(packed: u8) -> Self {
+        // 0x0 -> 0x00, 0x1 -> 0x11, ..., 0xf -> 0xff
+        Self {
+            peak: WaveformVal((packed >> 4) * 0x11),
+            energy: WaveformVal((packed & 0x0f) * 0x11),
+        }
+    }
+
+    /// Default threshold of [`Self::is_clipped_above()`], i.e. full scale
+    pub const DEFAULT_CLIPPING_THRESHOLD: f32 = 1.0;
+
+    /// Check if the peak has reached full scale
+    ///
+    /// The peak is quantized, i.e. all peaks in the top bucket
+    /// starting at `255/256` are considered as clipped.
+    #[must_use]
+    pub const fn is_clipped(&self) -> bool {
+        self.peak.0 == WaveformVal::MAX_VAL
+    }
+
+    /// Check if the peak has reached a threshold below full scale
+    ///
+    /// Some workflows consider peaks above, e.g., -0.1 dBFS (`0.989`) as
+    /// clipped. The peak is quantized, i.e. it is considered as clipped
+    /// if its bucket contains the threshold or is above. The resolution
+    /// is thus limited to `1/256`.
+    ///
+    /// [`Self::is_clipped()`] is equivalent to a threshold of
+    /// [`Self::DEFAULT_CLIPPING_THRESHOLD`].
+    #[must_use]
+    pub fn is_clipped_above(&self, threshold: f32) -> bool {
+        debug_assert!(threshold >= 0.0);
+        self.peak >= WaveformVal::from_f32(threshold)
+    }
+}
+
+impl From<WaveformBin> for (f32, f32) {
+    /// Peak and energy values
+    fn from(from: WaveformBin) -> Self {
+        let WaveformBin { peak, energy } = from;
+        (peak.to_f32(), energy.to_f32())
+    }
+}
+
+impl From<(f32, f32)> for WaveformBin {
+    /// See [`WaveformBin::from_f32s()`]
+    fn from((peak, energy): (f32, f32)) -> Self {
+        Self::from_f32s(peak, energy)
+    }
+}
+
+/// Peak and energy values before quantization
+///
+/// Allows to quantize the values independently, e.g. with a
+/// different resolution for peak and energy.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WaveformBinF32 {
+    /// Clamped, absolute peak value in the range `0..=1`
+    pub peak: f32,
+
+    /// Clamped and scaled RMS value in the range `0..=1`.
+    pub energy: f32,
+}
+
+/// Clamp a value into the range `0..=1`
+///
+/// Also reports if the value has been out of range. Not-a-number
+/// values are considered as out of range and mapped to `0.0`.
+fn clamp_reporting(val: f32) -> (f32, bool) {
+    if val.is_nan() {
+        return (0.0, true);
+    }
+    let clamped = val.clamp(0.0, 1.0);
+    #[allow(clippy::float_cmp)] // Exact comparison is intended
+    let saturated = clamped != val;
+    (clamped, saturated)
+}
+
+/// Shift right and round to nearest, ties to even
+const fn shift_right_rounded(val: u32, shift: u32) -> u32 {
+    debug_assert!(shift > 0);
+    debug_assert!(shift < 32);
+    let half = 1 << (shift - 1);
+    let remainder = val & ((1 << shift) - 1);
+    let shifted = val >> shift;
+    if remainder > half || (remainder == half && shifted & 1 == 1) {
+        shifted + 1
+    } else {
+        shifted
+    }
+}
+
+/// Bits of the IEEE 754 half-precision value nearest to `val`
+///
+/// Values that are too large overflow to infinity. All `NaN` values are
+/// mapped to the same quiet `NaN`.
+fn f32_to_f16_bits(val: f32) -> u16 {
+    if val.is_nan() {
+        return 0x7e00;
+    }
+    let bits = val.to_bits();
+    let sign = (bits >> 16) & 0x8000;
+    #[allow(clippy::cast_possible_wrap)]
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127;
+    let mantissa = bits & 0x7f_ffff;
+    let half_bits = if exponent > 15 {
+        // Overflow, including infinity
+        0x7c00
+    } else if exponent < -25 {
+        // Underflow, including zero
+        0
+    } else if exponent < -14 {
+        // Subnormal
+        #[allow(clippy::cast_sign_loss)]
+        let shift = (13 - 14 - exponent) as u32;
+        shift_right_rounded(mantissa | 0x80_0000, shift)
+    } else {
+        // Normal, the rounding might carry over into the exponent
+        #[allow(clippy::cast_sign_loss)]
+        let half_exponent = (exponent + 15) as u32;
+        (half_exponent << 10) + shift_right_rounded(mantissa, 13)
+    };
+    #[allow(clippy::cast_possible_truncation)]
+    let half_bits = (sign | half_bits) as u16;
+    half_bits
+}
+
+impl WaveformBinF32 {
+    pub(crate) fn clamp_energy(self) -> Self {
+        Self {
+            energy: self.energy.min(1.0),
+            ..self
+        }
+    }
+
+    /// Clamp all values into the range `0..=1`
+    ///
+    /// Also reports if any value has been out of range, e.g. after
+    /// applying a gain. Saturated values would be clipped when
+    /// quantizing them.
+    #[must_use]
+    pub fn clamp_reporting(&self) -> (Self, bool) {
+        let Self { peak, energy } = *self;
+        let (peak, peak_saturated) = clamp_reporting(peak);
+        let (energy, energy_saturated) = clamp_reporting(energy);
+        (Self { peak, energy }, peak_saturated || energy_saturated)
+    }
+
+    /// Convert into half-precision floating-point values
+    ///
+    /// Returns the bits of `[peak, energy]` as IEEE 754 binary16,
+    /// e.g. for uploading into a GPU texture. The values are much more
+    /// precise than [`Self::quantize()`] at half the size of `f32`.
+    /// The values are not clamped. Values above the largest finite
+    /// half-precision value `65504` overflow to infinity.
+    #[must_use]
+    pub fn to_f16_bits(&self) -> [u16; 2] {
+        let Self { peak, energy } = *self;
+        [f32_to_f16_bits(peak), f32_to_f16_bits(energy)]
+    }
+
+    /// Serialize the half-precision values, see [`Self::to_f16_bits()`]
+    ///
+    /// The byte order is always little-endian, independent of the target.
+    #[must_use]
+    pub fn to_f16_le_bytes(&self) -> [u8; 4] {
+        let [peak, energy] = self.to_f16_bits().map(u16::to_le_bytes);
+        [peak[0], peak[1], energy[0], energy[1]]
+    }
+
+    /// Serialize the values in the order `peak`, `energy`
+    ///
+    /// The byte order is always little-endian, independent of the target.
+    #[must_use]
+    pub fn to_le_bytes(&self) -> [u8; 8] {
+        let Self { peak, energy } = *self;
+        let mut bytes = [0; 8];
+        bytes[..4].copy_from_slice(&peak.to_le_bytes());
+        bytes[4..].copy_from_slice(&energy.to_le_bytes());
+        bytes
+    }
+
+    /// Inverse of [`Self::to_le_bytes()`]
+    #[must_use]
+    pub fn from_le_bytes(bytes: [u8; 8]) -> Self {
+        let [p0, p1, p2, p3, e0, e1, e2, e3] = bytes;
+        Self {
+            peak: f32::from_le_bytes([p0, p1, p2, p3]),
+            energy: f32::from_le_bytes([e0, e1, e2, e3]),
+        }
+    }
+
+    /// Quantize both values
+    ///
+    /// Values greater than `1.0` are saturated. Negative and `NaN`
+    /// values are mapped to `0.0`.
+    #[must_use]
+    pub fn quantize(&self) -> WaveformBin {
+        let Self { peak, energy } = *self;
+        WaveformBin {
+            peak: WaveformVal::from_f32(peak.max(0.0)),
+            energy: WaveformVal::from_f32(energy.max(0.0)),
+        }
+    }
+
+    /// Quantize with µ-law companding
+    ///
+    /// Out of range values are treated like by [`Self::quantize()`].
+    ///
+    /// See also: [`WaveformVal::from_f32_mu_law()`]
+    #[must_use]
+    pub fn quantize_mu_law(&self) -> WaveformBin {
+        let Self { peak, energy } = *self;
+        WaveformBin {
+            peak: WaveformVal::from_f32_mu_law(peak.max(0.0)),
+            energy: WaveformVal::from_f32_mu_law(energy.max(0.0)),
+        }
+    }
+}
+
+/// Filtered peak and energy values before quantization
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FilteredWaveformBinF32 {
+    pub all: WaveformBinF32,
+    pub low: WaveformBinF32,
+    pub mid: WaveformBinF32,
+    pub high: WaveformBinF32,
+}
+
+impl FilteredWaveformBinF32 {
+    pub(crate) fn clamp_energy(self) -> Self {
+        let Self {
+            all,
+            low,
+            mid,
+            high,
+        } = self;
+        Self {
+            all: all.clamp_energy(),
+            low: low.clamp_energy(),
+            mid: mid.clamp_energy(),
+            high: high.clamp_energy(),
+        }
+    }
+
+    pub(crate) fn max_energy(&self) -> f32 {
+        let Self {
+            all,
+            low,
+            mid,
+            high,
+        } = self;
+        all.energy.max(low.energy).max(mid.energy).max(high.energy)
+    }
+
+    /// Clamp all values of all bands into the range `0..=1`
+    ///
+    /// See also: [`WaveformBinF32::clamp_reporting()`]
+    #[must_use]
+    pub fn clamp_reporting(&self) -> (Self, bool) {
+        let (all, all_saturated) = self.all.clamp_reporting();
+        let (low, low_saturated) = self.low.clamp_reporting();
+        let (mid, mid_saturated) = self.mid.clamp_reporting();
+        let (high, high_saturated) = self.high.clamp_reporting();
+        let clamped = Self {
+            all,
+            low,
+            mid,
+            high,
+        };
+        let saturated = all_saturated || low_saturated || mid_saturated || high_saturated;
+        (clamped, saturated)
+    }
+
+    /// Convert into half-precision floating-point values
+    ///
+    /// Returns the bits of the peak and energy values of all bands in
+    /// the order `all`, `low`, `mid`, `high`, see [`WaveformBinF32::to_f16_bits()`].
+    #[must_use]
+    pub fn to_f16_bits(&self) -> [u16; 8] {
+        let Self {
+            all,
+            low,
+            mid,
+            high,
+        } = self;
+        let [all_peak, all_energy] = all.to_f16_bits();
+        let [low_peak, low_energy] = low.to_f16_bits();
+        let [mid_peak, mid_energy] = mid.to_f16_bits();
+        let [high_peak, high_energy] = high.to_f16_bits();
+        [
+            all_peak,
+            all_energy,
+            low_peak,
+            low_energy,
+            mid_peak,
+            mid_energy,
+            high_peak,
+            high_energy,
+        ]
+    }
+
+    /// Serialize the half-precision values of all bands
+    ///
+    /// The bands are stored in the order `all`, `low`, `mid`, `high`,
+    /// see [`WaveformBinF32::to_f16_le_bytes()`].
+    #[must_use]
+    pub fn to_f16_le_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        for (chunk, bin) in bytes.chunks_exact_mut(4).zip(self.bands()) {
+            chunk.copy_from_slice(&bin.to_f16_le_bytes());
+        }
+        bytes
+    }
+
+    /// Serialize the values of all bands
+    ///
+    /// The bands are stored in the order `all`, `low`, `mid`, `high`,
+    /// see [`WaveformBinF32::to_le_bytes()`].
+    #[must_use]
+    pub fn to_le_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        for (chunk, bin) in bytes.chunks_exact_mut(8).zip(self.bands()) {
+            chunk.copy_from_slice(&bin.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Inverse of [`Self::to_le_bytes()`]
+    #[must_use]
+    pub fn from_le_bytes(bytes: [u8; 32]) -> Self {
+        let mut bands = [WaveformBinF32::default(); 4];
+        for (bin, chunk) in bands.iter_mut().zip(bytes.chunks_exact(8)) {
+            let mut bin_bytes = [0; 8];
+            bin_bytes.copy_from_slice(chunk);
+            *bin = WaveformBinF32::from_le_bytes(bin_bytes);
+        }
+        let [all, low, mid, high] = bands;
+        Self {
+            all,
+            low,
+            mid,
+            high,
+        }
+    }
+
+    const fn bands(&self) -> [WaveformBinF32; 4] {
+        let Self {
+            all,
+            low,
+            mid,
+            high,
+        } = *self;
+        [all, low, mid, high]
+    }
+
+    #[must_use]
+    pub fn quantize(&self) -> FilteredWaveformBin {
+        let Self {
+            all,
+            low,
+            mid,
+            high,
+        } = self;
+        FilteredWaveformBin {
+            all: all.quantize(),
+            low: low.quantize(),
+            mid: mid.quantize(),
+            high: high.quantize(),
+        }
+    }
+
+    /// Quantize all bands with µ-law companding
+    ///
+    /// See also: [`WaveformVal::from_f32_mu_law()`]
+    #[must_use]
+    pub fn quantize_mu_law(&self) -> FilteredWaveformBin {
+        let Self {
+            all,
+            low,
+            mid,
+            high,
+        } = self;
+        FilteredWaveformBin {
+            all: all.quantize_mu_law(),
+            low: low.quantize_mu_law(),
+            mid: mid.quantize_mu_law(),
+            high: high.quantize_mu_law(),
+        }
+    }
+}
+
+/// Filtered peak and energy values of a window of samples
+///
+/// Bins are only emitted for non-empty windows of samples, i.e. every
+/// bin that is returned by [`WaveformFilter`](crate::WaveformFilter)
+/// contains at least a single sample. The [`Default`] value is
+/// indistinguishable from a bin of silent samples, see [`Self::is_silent()`].
 #[derive(Debug, Clone, Default)]
 pub struct FilteredWaveformBin {
     pub all: WaveformBin,
@@ -100,6 +841,46 @@ pub struct FilteredWaveformBin {
 }
 
 impl FilteredWaveformBin {
+    /// Aggregate multiple bins into a single bin
+    ///
+    /// Combines the bins of each band independently, see [`WaveformBin::combine()`].
+    #[must_use]
+    pub fn combine(bins: &[Self]) -> Self {
+        Self {
+            all: WaveformBin::combine_iter(bins.iter().map(|bin| bin.all)),
+            low: WaveformBin::combine_iter(bins.iter().map(|bin| bin.low)),
+            mid: WaveformBin::combine_iter(bins.iter().map(|bin| bin.mid)),
+            high: WaveformBin::combine_iter(bins.iter().map(|bin| bin.high)),
+        }
+    }
+
+    /// Mix two bins with an equal-power crossfade
+    ///
+    /// Mixes the bins of each band independently, see [`WaveformBin::mix()`].
+    #[must_use]
+    pub fn mix(&self, other: &Self, t: f32) -> Self {
+        Self {
+            all: self.all.mix(other.all, t),
+            low: self.low.mix(other.low, t),
+            mid: self.mid.mix(other.mid, t),
+            high: self.high.mix(other.high, t),
+        }
+    }
+
+    /// Check if all values of all bands are zero
+    ///
+    /// Applies to both bins of silent samples and the [`Default`] value.
+    #[must_use]
+    pub const fn is_silent(&self) -> bool {
+        let Self {
+            all,
+            low,
+            mid,
+            high,
+        } = self;
+        all.is_silent() && low.is_silent() && mid.is_silent() && high.is_silent()
+    }
+
     /// Peak values
     #[must_use]
     pub const fn peak(&self) -> FilteredWaveformVal {
@@ -117,6 +898,21 @@ impl FilteredWaveformBin {
         }
     }
 
+    /// The bins of the low, mid, and high band in this order
+    ///
+    /// Excludes the `all` band, e.g. for iterating over the bands that
+    /// are mapped to colors.
+    #[must_use]
+    pub const fn band_bins(&self) -> [WaveformBin; 3] {
+        let Self {
+            all: _,
+            low,
+            mid,
+            high,
+        } = self;
+        [*low, *mid, *high]
+    }
+
     /// Scaled RMS values
     #[must_use]
     pub const fn energy(&self) -> FilteredWaveformVal {
@@ -158,11 +954,170 @@ impl FilteredWaveformBin {
         debug_assert!(geometric_mean <= 1.0);
         geometric_mean / arithmetic_mean
     }
+
+    /// Spectral "color temperature"
+    ///
+    /// Returns a value in the range `0..=1` that is derived from the
+    /// distribution of the energy among the bands. `1.0` is maximally
+    /// warm (all energy in the low band) and `0.0` is maximally cool
+    /// (all energy in the high band). The mid band contributes neutrally.
+    /// Silence is mapped to the neutral value `0.5`.
+    #[must_use]
+    pub fn warmth(&self) -> f32 {
+        let FilteredWaveformVal {
+            all: _,
+            low,
+            mid,
+            high,
+        } = self.energy();
+        let low = low.to_f32();
+        let mid = mid.to_f32();
+        let high = high.to_f32();
+        let sum = low + mid + high;
+        if sum == 0.0 {
+            return 0.5;
+        }
+        (low + 0.5 * mid) / sum
+    }
+
+    /// The band with the highest energy
+    ///
+    /// Returns `None` if the energies of all bands are zero. Ties are
+    /// resolved in favor of the lower band.
+    #[must_use]
+    pub fn dominant_band(&self) -> Option<Band> {
+        let FilteredWaveformVal {
+            all: _,
+            low,
+            mid,
+            high,
+        } = self.energy();
+        let (band, energy) = max_band_energy(low, mid, high);
+        (!energy.is_zero()).then_some(band)
+    }
+
+    /// Approximation of the perceived loudness
+    ///
+    /// Follows [Stevens' power law](https://en.wikipedia.org/wiki/Stevens%27s_power_law)
+    /// on the `all` energy, i.e. the loudness doubles for every 10 dB and
+    /// values are proportional to sones. Since the energy is proportional
+    /// to the amplitude and not to the intensity the exponent is `0.6`
+    /// instead of `0.3`. The result is in the range `0..=1`.
+    ///
+    /// Only a rough approximation for visualization purposes that neither
+    /// considers frequency weighting nor masking.
+    #[must_use]
+    pub fn perceived_loudness(&self) -> f32 {
+        self.all.energy.to_f32().powf(0.6)
+    }
+
+    /// Vertical extent of the `all` band for drawing a filled waveform
+    ///
+    /// Returns `(top, bottom)` in the range `-1.0..=1.0`. Peaks are
+    /// unsigned and the outline is symmetric, i.e. `(peak, -peak)`.
+    #[must_use]
+    pub fn outline(&self) -> (f32, f32) {
+        let peak = self.all.peak.to_f32();
+        (peak, -peak)
+    }
+
+    /// RGB color of the band energies with brightness following the `all` peak
+    ///
+    /// The hue is derived from the ratios of the band energies at full
+    /// brightness, see [`FilteredWaveformVal::spectral_rgb_color()`]. All
+    /// components are then scaled by the `all` peak. This produces crisp
+    /// colors that follow transients.
+    #[must_use]
+    pub fn color_band_energy_scaled_by_all_peak(&self) -> LinearRgb {
+        let LinearRgb(rgb) = self.energy().spectral_rgb_color();
+        let scale = self.all.peak.to_f32();
+        LinearRgb(rgb.map(|val| val * scale))
+    }
+
+    /// Energy-weighted mean of the band positions
+    ///
+    /// Returns a value in the range `0..=1` with the low band at `0.0`,
+    /// the mid band at `0.5`, and the high band at `1.0`. Higher values
+    /// indicate a brighter sound. The counterpart of [`Self::warmth()`].
+    /// Silence is mapped to the neutral value `0.5`.
+    ///
+    /// <https://en.wikipedia.org/wiki/Spectral_centroid>
+    #[must_use]
+    pub fn spectral_centroid(&self) -> f32 {
+        let FilteredWaveformVal {
+            all: _,
+            low,
+            mid,
+            high,
+        } = self.energy();
+        let low = low.to_f32();
+        let mid = mid.to_f32();
+        let high = high.to_f32();
+        let sum = low + mid + high;
+        if sum == 0.0 {
+            return 0.5;
+        }
+        (0.5 * mid + high) / sum
+    }
+
+    /// Spectral flatness weighted by the `all` energy
+    ///
+    /// The ratios between the bands are unstable for quiet bins. The
+    /// result is blended from the [`Self::spectral_flatness()`] towards
+    /// `1.0` (perfectly flat spectrum) proportional to how quiet the bin is.
+    #[must_use]
+    pub fn spectral_flatness_weighted(&self) -> f32 {
+        let weight = self.all.energy.to_f32();
+        weight * self.spectral_flatness() + (1.0 - weight)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::WaveformVal;
+    use crate::{
+        test_util::{bin_with_all_energy, bin_with_band_energies, bin_with_energies},
+        LinearRgb, ThreeBandFilterFreqConfig,
+    };
+
+    use super::{
+        f32_to_f16_bits, Band, FilteredWaveformBin, FilteredWaveformBinF32, FilteredWaveformVal,
+        SpectralColorWeights, WaveformBin, WaveformBinF32, WaveformVal,
+    };
+
+    #[test]
+    fn waveform_val_mu_law_round_trip() {
+        assert_eq!(WaveformVal(0), WaveformVal::from_f32_mu_law(0.0));
+        assert_eq!(WaveformVal(u8::MAX), WaveformVal::from_f32_mu_law(1.0));
+        assert_eq!(WaveformVal(u8::MAX), WaveformVal::from_f32_mu_law(2.0));
+        for i in u8::MIN..=u8::MAX {
+            let val = WaveformVal(i);
+            assert_eq!(val, WaveformVal::from_f32_mu_law(val.to_f32_mu_law()));
+        }
+        for i in 0..=1000u16 {
+            let val = f32::from(i) / 1000.0;
+            let round_trip = WaveformVal::from_f32_mu_law(val).to_f32_mu_law();
+            // Half a step in the companded domain
+            assert!((round_trip - val).abs() <= 0.011 * (val + 1.0 / 255.0));
+        }
+        // Finer resolution for quiet values
+        assert!(WaveformVal(1).to_f32_mu_law() < WaveformVal(1).to_f32() / 10.0);
+    }
+
+    #[test]
+    fn waveform_val_from_db() {
+        assert_eq!(WaveformVal(0), WaveformVal::from_db(-60.0, -60.0));
+        assert_eq!(
+            WaveformVal(0),
+            WaveformVal::from_db(f32::NEG_INFINITY, -60.0)
+        );
+        assert_eq!(WaveformVal(u8::MAX), WaveformVal::from_db(0.0, -60.0));
+        assert_eq!(WaveformVal(u8::MAX), WaveformVal::from_db(6.0, -60.0));
+        for val in (1..=u8::MAX).map(WaveformVal) {
+            assert_eq!(val, WaveformVal::from_db(val.to_db(), -60.0));
+        }
+        // Above the floor, but still quantized to zero
+        assert_eq!(WaveformVal(0), WaveformVal::from_db(-50.0, -60.0));
+    }
 
     #[test]
     fn waveform_val_from_f32() {
@@ -179,6 +1134,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn waveform_val_from_f32_uniform_buckets() {
+        for val in WaveformVal::MIN_VAL..=WaveformVal::MAX_VAL {
+            let lower_bound = f32::from(val) / 256.0;
+            let upper_bound = (f32::from(val) + 1.0) / 256.0;
+            assert_eq!(WaveformVal(val), WaveformVal::from_f32(lower_bound));
+            assert_eq!(
+                WaveformVal(val),
+                WaveformVal::from_f32(upper_bound - upper_bound * f32::EPSILON)
+            );
+        }
+    }
+
+    #[test]
+    fn waveform_val_from_f32_top_bucket() {
+        let max = WaveformVal(WaveformVal::MAX_VAL);
+        assert_eq!(max, WaveformVal::from_f32(1.0));
+        assert_eq!(max, WaveformVal::from_f32(1.0 - f32::EPSILON));
+        assert_eq!(max, WaveformVal::from_f32(0.999));
+        assert_eq!(max, WaveformVal::from_f32(255.0 / 256.0));
+        assert_eq!(
+            WaveformVal(WaveformVal::MAX_VAL - 1),
+            WaveformVal::from_f32(255.0 / 256.0 - f32::EPSILON)
+        );
+        assert_eq!(max, WaveformVal::from_f32(1.5));
+    }
+
     #[test]
     fn waveform_val_to_from_f32() {
         for val in WaveformVal::MIN_VAL..=WaveformVal::MAX_VAL {
@@ -187,6 +1169,265 @@ mod tests {
         }
     }
 
+    #[test]
+    fn little_endian_bytes() {
+        let bin = WaveformBinF32 {
+            peak: 1.0,
+            energy: 0.5,
+        };
+        assert_eq!([0x00, 0x3c, 0x00, 0x38], bin.to_f16_le_bytes());
+        assert_eq!(
+            [0x00, 0x00, 0x80, 0x3f, 0x00, 0x00, 0x00, 0x3f],
+            bin.to_le_bytes()
+        );
+        assert_eq!(bin, WaveformBinF32::from_le_bytes(bin.to_le_bytes()));
+        let filtered_bin = FilteredWaveformBinF32 {
+            all: bin,
+            high: WaveformBinF32 {
+                peak: 0.25,
+                energy: 0.125,
+            },
+            ..Default::default()
+        };
+        let bytes = filtered_bin.to_le_bytes();
+        assert_eq!(bin.to_le_bytes(), bytes[..8]);
+        assert_eq!([0; 16], bytes[8..24]);
+        assert_eq!(filtered_bin, FilteredWaveformBinF32::from_le_bytes(bytes));
+        let f16_bytes = filtered_bin.to_f16_le_bytes();
+        assert_eq!(bin.to_f16_le_bytes(), f16_bytes[..4]);
+        assert_eq!([0x00, 0x34, 0x00, 0x30], f16_bytes[12..]);
+    }
+
+    #[test]
+    fn f16_bits() {
+        assert_eq!(0x0000, f32_to_f16_bits(0.0));
+        assert_eq!(0x3c00, f32_to_f16_bits(1.0));
+        assert_eq!(0x3800, f32_to_f16_bits(0.5));
+        assert_eq!(0x3555, f32_to_f16_bits(1.0 / 3.0));
+        assert_eq!(0x2e66, f32_to_f16_bits(0.1));
+        // Smallest normal and subnormal values
+        assert_eq!(0x0400, f32_to_f16_bits(2f32.powi(-14)));
+        assert_eq!(0x0001, f32_to_f16_bits(2f32.powi(-24)));
+        assert_eq!(0x0000, f32_to_f16_bits(2f32.powi(-26)));
+        // Rounding up into the next exponent
+        assert_eq!(0x3c00, f32_to_f16_bits(1.0 - f32::EPSILON));
+        // Out of range values, e.g. after applying a gain
+        assert_eq!(0x4000, f32_to_f16_bits(2.0));
+        assert_eq!(0x7bff, f32_to_f16_bits(65504.0));
+        assert_eq!(0x7c00, f32_to_f16_bits(65520.0));
+        assert_eq!(0x7c00, f32_to_f16_bits(1e6));
+        assert_eq!(0x7c00, f32_to_f16_bits(f32::INFINITY));
+        assert_eq!(0xb800, f32_to_f16_bits(-0.5));
+        assert_eq!(0x8000, f32_to_f16_bits(-0.0));
+        assert_eq!(0xfc00, f32_to_f16_bits(-1e6));
+        assert_eq!(0x7e00, f32_to_f16_bits(f32::NAN));
+        assert_eq!(
+            [0x3c00, 0x3800],
+            WaveformBinF32 {
+                peak: 1.0,
+                energy: 0.5
+            }
+            .to_f16_bits()
+        );
+    }
+
+    #[test]
+    fn waveform_val_lerp() {
+        for val in WaveformVal::MIN_VAL..=WaveformVal::MAX_VAL {
+            let start = WaveformVal(val);
+            let end = WaveformVal(WaveformVal::MAX_VAL - val);
+            assert_eq!(start, start.lerp(end, 0.0));
+            assert_eq!(end, start.lerp(end, 1.0));
+        }
+        let min = WaveformVal(WaveformVal::MIN_VAL);
+        let max = WaveformVal(WaveformVal::MAX_VAL);
+        assert_eq!(WaveformVal(128), min.lerp(max, 0.5));
+        assert_eq!(WaveformVal(128), max.lerp(min, 0.5));
+        assert_eq!(WaveformVal(96), WaveformVal(64).lerp(WaveformVal(128), 0.5));
+    }
+
+    #[test]
+    fn packed_byte_round_trip() {
+        assert_eq!(
+            0xf0,
+            WaveformBin {
+                peak: WaveformVal(u8::MAX),
+                energy: WaveformVal(0x0f),
+            }
+            .to_packed_byte()
+        );
+        for peak in 0..=u8::MAX {
+            for energy in [0, 1, 15, 16, 127, 128, 254, 255] {
+                let bin = WaveformBin {
+                    peak: WaveformVal(peak),
+                    energy: WaveformVal(energy),
+                };
+                let unpacked = WaveformBin::from_packed_byte(bin.to_packed_byte());
+                assert!(peak.abs_diff(unpacked.peak.0) <= 15);
+                assert!(energy.abs_diff(unpacked.energy.0) <= 15);
+                assert_eq!(
+                    bin.to_packed_byte(),
+                    WaveformBin::from_packed_byte(bin.to_packed_byte()).to_packed_byte()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn clipping_threshold() {
+        let bin = |peak| WaveformBin {
+            peak: WaveformVal(peak),
+            energy: WaveformVal(0),
+        };
+        for peak in 0..=u8::MAX {
+            assert_eq!(
+                bin(peak).is_clipped(),
+                bin(peak).is_clipped_above(WaveformBin::DEFAULT_CLIPPING_THRESHOLD)
+            );
+        }
+        // -0.1 dBFS
+        let threshold = 0.989;
+        assert!(!bin(252).is_clipped_above(threshold));
+        assert!(bin(253).is_clipped_above(threshold));
+        assert!(bin(254).is_clipped_above(threshold));
+    }
+
+    #[test]
+    fn band_bins() {
+        let band = |val| WaveformBin {
+            peak: WaveformVal(val),
+            energy: WaveformVal(val / 2),
+        };
+        let bin = FilteredWaveformBin {
+            all: band(255),
+            low: band(10),
+            mid: band(20),
+            high: band(30),
+        };
+        let [low, mid, high] = bin.band_bins();
+        assert_eq!(WaveformVal(10), low.peak);
+        assert_eq!(WaveformVal(10), mid.energy);
+        assert_eq!(WaveformVal(30), high.peak);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn waveform_bin_f32_pair_round_trip() {
+        let bin = WaveformBin::from_f32s(0.5, 0.25);
+        let (peak, energy) = bin.into();
+        let round_trip = WaveformBin::from((peak, energy));
+        assert_eq!(bin.peak, round_trip.peak);
+        assert_eq!(bin.energy, round_trip.energy);
+        // Out of range values are clamped
+        let (peak, energy) = WaveformBin::from_f32s(1.5, f32::NAN).into();
+        assert_eq!(1.0, peak);
+        assert_eq!(0.0, energy);
+        let (peak, _) = <(f32, f32)>::from(WaveformBin::from_f32s(-1.0, 0.0));
+        assert_eq!(0.0, peak);
+    }
+
+    #[test]
+    fn mix_waveform_bins() {
+        let bin = WaveformBin {
+            peak: WaveformVal(100),
+            energy: WaveformVal(200),
+        };
+        let silence = WaveformBin::default();
+        for t in [0.0, -1.0] {
+            let mixed = bin.mix(silence, t);
+            assert_eq!(bin.peak, mixed.peak);
+            assert_eq!(bin.energy, mixed.energy);
+        }
+        assert!(bin.mix(silence, 1.0).is_silent());
+        // Equal power
+        let mixed = bin.mix(bin, 0.5);
+        assert!(bin.energy.0.abs_diff(mixed.energy.0) <= 1);
+        assert!(mixed.peak > bin.peak);
+    }
+
+    #[test]
+    fn combine_waveform_bins() {
+        assert!(WaveformBin::combine(&[]).is_silent());
+        let bin = WaveformBin {
+            peak: WaveformVal(200),
+            energy: WaveformVal(100),
+        };
+        let combined = WaveformBin::combine(&[bin]);
+        assert_eq!(bin.peak, combined.peak);
+        assert_eq!(bin.energy, combined.energy);
+        let combined = WaveformBin::combine(&[
+            bin,
+            WaveformBin {
+                peak: WaveformVal(255),
+                energy: WaveformVal(0),
+            },
+        ]);
+        assert_eq!(WaveformVal(255), combined.peak);
+        // RMS: 100 / sqrt(2) = 70.7
+        assert_eq!(WaveformVal(70), combined.energy);
+    }
+
+    #[test]
+    fn clamp_reporting() {
+        let bin = FilteredWaveformBinF32 {
+            all: WaveformBinF32 {
+                peak: 1.0,
+                energy: 0.5,
+            },
+            ..Default::default()
+        };
+        assert_eq!((bin, false), bin.clamp_reporting());
+        let saturated = FilteredWaveformBinF32 {
+            mid: WaveformBinF32 {
+                peak: 1.5,
+                energy: 0.75,
+            },
+            ..bin
+        };
+        let (clamped, is_saturated) = saturated.clamp_reporting();
+        assert!(is_saturated);
+        assert_eq!(
+            FilteredWaveformBinF32 {
+                mid: WaveformBinF32 {
+                    peak: 1.0,
+                    energy: 0.75,
+                },
+                ..bin
+            },
+            clamped
+        );
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn quantize_out_of_range() {
+        for bin in [
+            WaveformBinF32 {
+                peak: -0.5,
+                energy: f32::NAN,
+            },
+            WaveformBinF32 {
+                peak: 1.5,
+                energy: f32::INFINITY,
+            },
+        ] {
+            let (expected, _) = bin.clamp_reporting();
+            assert_eq!(expected.peak, bin.quantize().peak.to_f32());
+            assert_eq!(expected.energy, bin.quantize().energy.to_f32());
+            assert_eq!(expected.peak, bin.quantize_mu_law().peak.to_f32_mu_law());
+        }
+    }
+
+    #[test]
+    fn warmth() {
+        assert!((bin_with_band_energies(0, 0, 0).warmth() - 0.5).abs() < f32::EPSILON);
+        assert!((bin_with_band_energies(100, 0, 0).warmth() - 1.0).abs() < f32::EPSILON);
+        assert!(bin_with_band_energies(0, 0, 100).warmth().abs() < f32::EPSILON);
+        assert!((bin_with_band_energies(0, 100, 0).warmth() - 0.5).abs() < f32::EPSILON);
+        assert!((bin_with_band_energies(50, 50, 50).warmth() - 0.5).abs() < f32::EPSILON);
+        assert!(bin_with_band_energies(200, 100, 50).warmth() > 0.5);
+    }
+
     #[test]
     fn spectral_flatness_one() {
         for val in WaveformVal::MIN_VAL..=WaveformVal::MAX_VAL {
@@ -213,4 +1454,204 @@ mod tests {
             assert!(spectral_flatness > 0.999_999);
         }
     }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn spectral_flatness_weighted() {
+        // Full weight for a full-scale bin
+        let loud = bin_with_energies(u8::MAX, 200, 50, 10);
+        let flatness = loud.spectral_flatness();
+        assert!(flatness < 0.9);
+        assert_eq!(flatness, loud.spectral_flatness_weighted());
+        // Quieter bins are blended towards a flat spectrum
+        let quieter = bin_with_energies(128, 200, 50, 10);
+        assert_eq!(flatness, quieter.spectral_flatness());
+        let weighted = quieter.spectral_flatness_weighted();
+        assert!(weighted > flatness);
+        assert!(weighted < 1.0);
+        assert_eq!(
+            1.0,
+            bin_with_energies(0, 200, 50, 10).spectral_flatness_weighted()
+        );
+        // A flat spectrum is not affected by the weight
+        for all in [0, 128, u8::MAX] {
+            let flat = bin_with_energies(all, 100, 100, 100);
+            assert!((flat.spectral_flatness_weighted() - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn spectral_centroid_is_inverse_warmth() {
+        for (low, mid, high) in [(0, 0, 0), (10, 0, 0), (0, 10, 0), (0, 0, 10), (3, 50, 200)] {
+            let bin = bin_with_band_energies(low, mid, high);
+            assert!((1.0 - bin.warmth() - bin.spectral_centroid()).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn dominant_band() {
+        assert_eq!(None, bin_with_band_energies(0, 0, 0).dominant_band());
+        assert_eq!(
+            Some(Band::Low),
+            bin_with_band_energies(1, 0, 0).dominant_band()
+        );
+        assert_eq!(
+            Some(Band::Mid),
+            bin_with_band_energies(1, 2, 1).dominant_band()
+        );
+        assert_eq!(
+            Some(Band::High),
+            bin_with_band_energies(1, 2, 3).dominant_band()
+        );
+        assert_eq!(
+            Some(Band::Mid),
+            bin_with_band_energies(1, 3, 3).dominant_band()
+        );
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn perceived_loudness() {
+        assert_eq!(0.0, bin_with_all_energy(0).perceived_loudness());
+        assert_eq!(1.0, bin_with_all_energy(u8::MAX).perceived_loudness());
+        // +10 dB doubles the loudness
+        let quiet = bin_with_all_energy(25).perceived_loudness();
+        let loud = bin_with_all_energy(79).perceived_loudness();
+        assert!((loud / quiet - 2.0).abs() < 0.01);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn outline() {
+        assert_eq!((0.0, -0.0), FilteredWaveformBin::default().outline());
+        let bin = FilteredWaveformBin {
+            all: WaveformBin {
+                peak: WaveformVal(u8::MAX),
+                energy: WaveformVal(0),
+            },
+            ..Default::default()
+        };
+        assert_eq!((1.0, -1.0), bin.outline());
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn color_band_energy_scaled_by_all_peak() {
+        let bin = FilteredWaveformBin {
+            all: WaveformBin {
+                peak: WaveformVal(51),
+                energy: WaveformVal(10),
+            },
+            low: WaveformBin {
+                peak: WaveformVal(0),
+                energy: WaveformVal(100),
+            },
+            mid: WaveformBin {
+                peak: WaveformVal(0),
+                energy: WaveformVal(50),
+            },
+            high: WaveformBin::default(),
+        };
+        let LinearRgb([red, green, blue]) = bin.color_band_energy_scaled_by_all_peak();
+        assert_eq!(0.2, red);
+        assert_eq!(0.1, green);
+        assert_eq!(0.0, blue);
+        assert_eq!(
+            LinearRgb([0.0; 3]),
+            FilteredWaveformBin::default().color_band_energy_scaled_by_all_peak()
+        );
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn spectral_rgb_color_weighted() {
+        let val = FilteredWaveformVal {
+            all: WaveformVal(0),
+            low: WaveformVal(u8::MAX),
+            mid: WaveformVal(u8::MAX),
+            high: WaveformVal(0),
+        };
+        assert_eq!(LinearRgb([1.0, 1.0, 0.0]), val.spectral_rgb_color());
+        assert_eq!(
+            LinearRgb([0.5, 1.0, 0.0]),
+            val.spectral_rgb_color_weighted(&SpectralColorWeights::DEFAULT)
+        );
+        assert_eq!(
+            LinearRgb([0.0; 3]),
+            FilteredWaveformVal::default().spectral_rgb_color_weighted(&Default::default())
+        );
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn spectral_rgb_color_crossfaded() {
+        let val = FilteredWaveformVal {
+            all: WaveformVal(0),
+            low: WaveformVal(255),
+            mid: WaveformVal(0),
+            high: WaveformVal(0),
+        };
+        // Crossover frequencies one octave apart
+        let filter_freqs = ThreeBandFilterFreqConfig {
+            low_lp_hz: 200.0,
+            low_hp_hz: 100.0,
+            high_lp_hz: 2000.0,
+            high_hp_hz: 1000.0,
+        };
+        let LinearRgb([red, green, blue]) = val.spectral_rgb_color_crossfaded(&filter_freqs);
+        assert_eq!(1.0, red);
+        assert!((green - 0.5).abs() < 1e-6);
+        assert_eq!(0.0, blue);
+        // Without overlap
+        let filter_freqs = ThreeBandFilterFreqConfig {
+            low_lp_hz: 200.0,
+            low_hp_hz: 200.0,
+            high_lp_hz: 2000.0,
+            high_hp_hz: 2000.0,
+        };
+        assert_eq!(
+            val.spectral_rgb_color(),
+            val.spectral_rgb_color_crossfaded(&filter_freqs)
+        );
+        assert_eq!(
+            LinearRgb([0.0; 3]),
+            FilteredWaveformVal::default().spectral_rgb_color_crossfaded(&filter_freqs)
+        );
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn spectral_rgb_color_capped() {
+        let val = FilteredWaveformVal {
+            all: WaveformVal(0),
+            low: WaveformVal(255),
+            mid: WaveformVal(51),
+            high: WaveformVal(0),
+        };
+        let LinearRgb([red, green, blue]) = val.spectral_rgb_color_capped(0.5);
+        assert_eq!(1.0, red);
+        assert!((green - 0.4).abs() < 1e-6);
+        assert_eq!(0.0, blue);
+        // The uncapped color is normalized by the low band
+        let LinearRgb([_, green, _]) = val.spectral_rgb_color();
+        assert!((green - 0.2).abs() < 1e-6);
+        assert_eq!(LinearRgb([0.0; 3]), val.spectral_rgb_color_capped(0.0));
+    }
+
+    #[test]
+    fn spectral_rgb_color_all_with_luminance() {
+        let val = FilteredWaveformVal {
+            all: WaveformVal(u8::MAX),
+            low: WaveformVal(u8::MAX),
+            mid: WaveformVal(u8::MAX),
+            high: WaveformVal(u8::MAX),
+        };
+        let (color, luminance) = val.spectral_rgb_color_all_with_luminance();
+        assert_eq!(val.spectral_rgb_color_all(), color);
+        assert!((luminance - 1.0).abs() < 1e-6);
+        let (color, luminance) =
+            FilteredWaveformVal::default().spectral_rgb_color_all_with_luminance();
+        assert_eq!(LinearRgb([0.0; 3]), color);
+        assert!(luminance.abs() < 1e-6);
+    }
 }
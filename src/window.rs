@@ -0,0 +1,296 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Windowed processing of bins
+
+use std::{f32::consts::TAU, sync::Arc};
+
+use crate::{
+    filter::{
+        assert_valid_filter_params, BinBoundaries, FilteredSample, ThreeBandFilterBank,
+        ThreeBandFilterCoefficients,
+    },
+    AllBandSource, EnergyMetric, FilteredEnergyScale, FilteredWaveformBin, FilteredWaveformBinF32,
+    WaveformBinF32, WaveformFilterConfig,
+};
+
+/// Weighting of the samples within a bin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    /// Uniform weights
+    ///
+    /// Equivalent to [`WaveformFilter`](crate::WaveformFilter).
+    Rectangular,
+
+    /// <https://en.wikipedia.org/wiki/Hann_function>
+    Hann,
+
+    /// <https://en.wikipedia.org/wiki/Window_function#Hann_and_Hamming_windows>
+    Hamming,
+}
+
+impl Window {
+    pub const DEFAULT: Self = Self::Rectangular;
+
+    /// Weight of the sample at `index` within a bin of `len` samples
+    fn weight(self, index: usize, len: usize) -> f32 {
+        debug_assert!(index < len);
+        if len <= 1 {
+            return 1.0;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let phase = TAU * index as f32 / (len - 1) as f32;
+        match self {
+            Self::Rectangular => 1.0,
+            Self::Hann => 0.5 - 0.5 * phase.cos(),
+            Self::Hamming => 0.54 - 0.46 * phase.cos(),
+        }
+    }
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Weighted peak and mean square of a single band
+#[derive(Debug, Default)]
+struct WindowedAccumulator {
+    peak: f32,
+    weighted_sum: f64,
+    weighted_abs_sum: f64,
+    weighted_linear_sum: f64,
+}
+
+/// Sums of the weights for normalization
+#[derive(Debug, Default)]
+struct WeightSums {
+    linear: f64,
+    square: f64,
+}
+
+impl WindowedAccumulator {
+    /// Only accumulates the sums that are needed by the energy metric
+    fn add_sample(&mut self, sample: f32, weight: f32, energy_metric: EnergyMetric) {
+        let weighted_sample = sample * weight;
+        self.peak = self.peak.max(weighted_sample.abs());
+        let weighted_sample = f64::from(weighted_sample);
+        self.weighted_sum += weighted_sample * weighted_sample;
+        match energy_metric {
+            EnergyMetric::Rms | EnergyMetric::MeanSquare => (),
+            EnergyMetric::MeanAbs => self.weighted_abs_sum += weighted_sample.abs(),
+            EnergyMetric::Variance => self.weighted_linear_sum += weighted_sample,
+        }
+    }
+
+    fn finish(
+        self,
+        weight_sums: &WeightSums,
+        energy_scale: f32,
+        energy_metric: EnergyMetric,
+    ) -> WaveformBinF32 {
+        let Self {
+            peak,
+            weighted_sum,
+            weighted_abs_sum,
+            weighted_linear_sum,
+        } = self;
+        let WeightSums { linear, square } = *weight_sums;
+        let (mean_square, mean_abs, mean) = if linear > 0.0 && square > 0.0 {
+            (
+                weighted_sum / square,
+                weighted_abs_sum / linear,
+                weighted_linear_sum / linear,
+            )
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+        WaveformBinF32 {
+            peak: peak.min(1.0),
+            energy: energy_metric.unclamped_energy(mean_square, mean_abs, mean, energy_scale),
+        }
+        .clamp_energy()
+    }
+}
+
+/// Variant of [`WaveformFilter`](crate::WaveformFilter) that applies a
+/// window to the samples of each bin
+///
+/// The filtered samples of each bin are buffered and weighted by the
+/// window when finishing the bin. Tapering the bin edges reduces the
+/// influence of transients near the bin boundaries. The mean square is
+/// normalized by the sum of the squared weights and the means by the sum
+/// of the weights, i.e. a constant signal results in the same energy for
+/// all windows.
+///
+/// Peak hold and exponential energy averaging are not supported, i.e.
+/// [`WaveformFilterConfig::peak_hold`] and
+/// [`WaveformFilterConfig::energy_averaging`] are ignored.
+#[derive(Debug)]
+pub struct WindowedWaveformFilter {
+    window: Window,
+    bin_boundaries: BinBoundaries,
+    filter_bank: ThreeBandFilterBank,
+    energy_scale: FilteredEnergyScale,
+    energy_metric: EnergyMetric,
+    all_source: AllBandSource,
+    warmup_bins_count: usize,
+    filtered_samples: Vec<FilteredSample>,
+}
+
+impl WindowedWaveformFilter {
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn new(config: WaveformFilterConfig, window: Window) -> Self {
+        let samples_per_bin = config.samples_per_bin();
+        let energy_scale = config.effective_energy_scale();
+        let WaveformFilterConfig {
+            sample_rate_hz,
+            bins_per_sec: _,
+            filter_freqs,
+            peak_hold: _,
+            energy_averaging: _,
+            energy_scale: _,
+            energy_metric,
+            energy_reference_db: _,
+            all_source,
+            discard_warmup_bins,
+        } = config;
+        assert_valid_filter_params(sample_rate_hz, &filter_freqs);
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        let capacity = samples_per_bin.ceil() as usize;
+        Self {
+            window,
+            bin_boundaries: BinBoundaries::new(samples_per_bin),
+            filter_bank: ThreeBandFilterBank::new(Arc::new(
+                ThreeBandFilterCoefficients::new_unchecked(sample_rate_hz, filter_freqs),
+            )),
+            energy_scale,
+            energy_metric,
+            all_source,
+            warmup_bins_count: discard_warmup_bins,
+            filtered_samples: Vec::with_capacity(capacity),
+        }
+    }
+
+    fn finish_bin(&mut self) -> Option<FilteredWaveformBinF32> {
+        let len = self.filtered_samples.len();
+        if len == 0 {
+            return None;
+        }
+        if self.warmup_bins_count > 0 {
+            self.warmup_bins_count -= 1;
+            self.filtered_samples.clear();
+            return None;
+        }
+        let mut all = WindowedAccumulator::default();
+        let mut low = WindowedAccumulator::default();
+        let mut mid = WindowedAccumulator::default();
+        let mut high = WindowedAccumulator::default();
+        let mut weight_sums = WeightSums::default();
+        for (index, filtered_sample) in self.filtered_samples.drain(..).enumerate() {
+            let weight = self.window.weight(index, len);
+            weight_sums.linear += f64::from(weight);
+            weight_sums.square += f64::from(weight * weight);
+            all.add_sample(filtered_sample.all, weight, self.energy_metric);
+            low.add_sample(filtered_sample.low, weight, self.energy_metric);
+            mid.add_sample(filtered_sample.mid, weight, self.energy_metric);
+            high.add_sample(filtered_sample.high, weight, self.energy_metric);
+        }
+        let FilteredEnergyScale {
+            all: all_scale,
+            low: low_scale,
+            mid: mid_scale,
+            high: high_scale,
+        } = self.energy_scale;
+        let mut bin = FilteredWaveformBinF32 {
+            all: all.finish(&weight_sums, all_scale, self.energy_metric),
+            low: low.finish(&weight_sums, low_scale, self.energy_metric),
+            mid: mid.finish(&weight_sums, mid_scale, self.energy_metric),
+            high: high.finish(&weight_sums, high_scale, self.energy_metric),
+        };
+        // Squaring the clamped energies is equivalent to clamping the squares.
+        self.energy_metric.apply(&mut bin);
+        Some(bin)
+    }
+
+    pub fn add_sample(&mut self, sample: f32) -> Option<FilteredWaveformBin> {
+        self.add_sample_unquantized(sample)
+            .as_ref()
+            .map(FilteredWaveformBinF32::quantize)
+    }
+
+    /// Same as [`Self::add_sample()`] but without quantizing the bin
+    pub fn add_sample_unquantized(&mut self, sample: f32) -> Option<FilteredWaveformBinF32> {
+        let next_bin = if self.bin_boundaries.is_bin_complete() {
+            self.bin_boundaries.start_next_bin();
+            self.finish_bin()
+        } else {
+            None
+        };
+        let filtered_sample = self.all_source.apply(self.filter_bank.run(sample));
+        self.filtered_samples.push(filtered_sample);
+        self.bin_boundaries.add_sample();
+        next_bin
+    }
+
+    #[must_use]
+    pub fn finish(self) -> Option<FilteredWaveformBin> {
+        self.finish_unquantized()
+            .as_ref()
+            .map(FilteredWaveformBinF32::quantize)
+    }
+
+    /// Same as [`Self::finish()`] but without quantizing the bin
+    #[must_use]
+    pub fn finish_unquantized(mut self) -> Option<FilteredWaveformBinF32> {
+        self.finish_bin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compute_waveform, FilteredWaveformBin, WaveformFilterConfig};
+
+    use super::{Window, WindowedWaveformFilter};
+
+    fn compute_windowed(window: Window, samples: &[f32]) -> Vec<FilteredWaveformBin> {
+        let mut filter = WindowedWaveformFilter::new(WaveformFilterConfig::DEFAULT, window);
+        let mut bins = samples
+            .iter()
+            .filter_map(|&sample| filter.add_sample(sample))
+            .collect::<Vec<_>>();
+        bins.extend(filter.finish());
+        bins
+    }
+
+    #[test]
+    fn rectangular_window_equals_unwindowed() {
+        let samples = (0..10_000)
+            .map(|i| if i % 20 < 10 { 0.5 } else { -0.5 })
+            .collect::<Vec<_>>();
+        let expected_bins = compute_waveform(Default::default(), &samples);
+        let bins = compute_windowed(Window::Rectangular, &samples);
+        assert_eq!(expected_bins.len(), bins.len());
+        for (expected, actual) in expected_bins.iter().zip(&bins) {
+            assert_eq!(expected.all.peak, actual.all.peak);
+            assert!(expected.all.energy.0.abs_diff(actual.all.energy.0) <= 1);
+            assert!(expected.low.energy.0.abs_diff(actual.low.energy.0) <= 1);
+        }
+    }
+
+    #[test]
+    fn hann_window_suppresses_bin_edges() {
+        let mut samples = vec![0.0; 1000];
+        samples[0] = 1.0;
+        let bins = compute_windowed(Window::Rectangular, &samples);
+        assert!(!bins[0].all.peak.is_zero());
+        let bins = compute_windowed(Window::Hann, &samples);
+        assert!(bins[0].all.peak.is_zero());
+        let bins = compute_windowed(Window::Hamming, &samples);
+        assert!(!bins[0].all.peak.is_zero());
+        assert!(bins[0].all.peak.to_f32() < 0.1);
+    }
+}
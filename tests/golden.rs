@@ -0,0 +1,95 @@
+// SPDX-FileCopyrightText: The audio-viz authors
+// SPDX-License-Identifier: MPL-2.0
+
+//! Tripwire for numeric changes of the default output
+//!
+//! Compares the bins of a synthetic signal against a checked-in file.
+//! After intended changes of the output the file could be regenerated
+//! by running the test with the environment variable
+//! `AUDIO_VIZ_REGENERATE_GOLDEN` set, e.g.
+//!
+//! ```sh
+//! AUDIO_VIZ_REGENERATE_GOLDEN=1 cargo test --test golden
+//! ```
+
+use std::path::PathBuf;
+
+use audio_viz::{compute_waveform, FilteredWaveformBin, WaveformBin, WaveformFilterConfig};
+
+const REGENERATE_ENV_VAR: &str = "AUDIO_VIZ_REGENERATE_GOLDEN";
+
+const SAMPLE_RATE_HZ: u32 = 44_100;
+
+/// Deterministic signal without transcendental functions
+///
+/// A mix of square waves at different frequencies with a pseudo-random
+/// noise floor and a linear fade-in.
+fn synthetic_signal() -> Vec<f32> {
+    let sample_count = 2 * SAMPLE_RATE_HZ;
+    let mut noise_state = 0x1234_5678_u32;
+    (0..sample_count)
+        .map(|index| {
+            let square = |freq_hz: u32| {
+                let period = SAMPLE_RATE_HZ / freq_hz;
+                if index % period < period / 2 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            };
+            // Linear congruential generator
+            noise_state = noise_state
+                .wrapping_mul(1_664_525)
+                .wrapping_add(1_013_904_223);
+            #[allow(clippy::cast_precision_loss)]
+            let noise = (noise_state >> 8) as f32 / (1 << 24) as f32 - 0.5;
+            #[allow(clippy::cast_precision_loss)]
+            let fade_in = (index as f32 / SAMPLE_RATE_HZ as f32).min(1.0);
+            let mix = 0.3 * square(100) + 0.2 * square(800) + 0.1 * square(5000) + 0.05 * noise;
+            fade_in * mix
+        })
+        .collect()
+}
+
+fn encode_bins(bins: &[FilteredWaveformBin]) -> Vec<u8> {
+    fn encode_bin(WaveformBin { peak, energy }: WaveformBin) -> [u8; 2] {
+        [peak.into(), energy.into()]
+    }
+    bins.iter()
+        .flat_map(|bin| {
+            let FilteredWaveformBin {
+                all,
+                low,
+                mid,
+                high,
+            } = bin;
+            [*all, *low, *mid, *high].into_iter().flat_map(encode_bin)
+        })
+        .collect()
+}
+
+fn golden_file_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden/default_config.bin")
+}
+
+#[test]
+fn default_config_output_is_unchanged() {
+    let bins = compute_waveform(WaveformFilterConfig::DEFAULT, &synthetic_signal());
+    let encoded = encode_bins(&bins);
+    let path = golden_file_path();
+    if std::env::var_os(REGENERATE_ENV_VAR).is_some() {
+        std::fs::write(&path, &encoded).unwrap();
+        return;
+    }
+    let golden = std::fs::read(&path).unwrap();
+    assert_eq!(golden.len(), encoded.len(), "number of bins changed");
+    let first_mismatch = golden
+        .iter()
+        .zip(&encoded)
+        .position(|(lhs, rhs)| lhs != rhs);
+    assert!(
+        first_mismatch.is_none(),
+        "output changed at bin {}, regenerate with {REGENERATE_ENV_VAR}=1 if intended",
+        first_mismatch.unwrap_or_default() / 8,
+    );
+}